@@ -80,6 +80,7 @@ pub struct Config {
     min_version: Bound<String>,
     max_version: Bound<String>,
     extra_args: Vec<OsString>,
+    search_paths: Vec<PathBuf>,
     cargo_metadata: bool,
     env_metadata: bool,
     print_system_libs: bool,
@@ -94,6 +95,7 @@ pub struct Library {
     pub framework_paths: Vec<PathBuf>,
     pub include_paths: Vec<PathBuf>,
     pub defines: HashMap<String, Option<String>>,
+    pub ld_args: Vec<Vec<String>>,
     pub version: String,
     _priv: (),
 }
@@ -185,6 +187,7 @@ pub fn get_variable(package: &str, variable: &str) -> Result<String, Error> {
     Ok(str::from_utf8(&out).unwrap().trim_end().to_owned())
 }
 
+
 impl Config {
     /// Creates a new set of configuration options which are all initially set
     /// to "blank".
@@ -194,6 +197,7 @@ impl Config {
             min_version: Bound::Unbounded,
             max_version: Bound::Unbounded,
             extra_args: vec![],
+            search_paths: vec![],
             print_system_cflags: true,
             print_system_libs: true,
             cargo_metadata: true,
@@ -242,6 +246,31 @@ impl Config {
         self
     }
 
+    /// Look for `.pc` files in `path` before the directories listed in
+    /// `PKG_CONFIG_PATH`, without changing the environment of the process.
+    pub fn search_path<P: Into<PathBuf>>(&mut self, path: P) -> &mut Config {
+        self.search_paths.push(path.into());
+        self
+    }
+
+    /// Run `pkg-config` to list the packages a package requires, one
+    /// `name [op version]` per entry. With `private` the `Requires.private`
+    /// field is listed instead of `Requires`.
+    pub fn requires(&self, package: &str, private: bool) -> Result<Vec<String>, Error> {
+        let arg = if private {
+            "--print-requires-private"
+        } else {
+            "--print-requires"
+        };
+        let out = run(self.command(package, &[arg]))?;
+        Ok(str::from_utf8(&out)
+            .unwrap()
+            .lines()
+            .map(|line| line.trim().to_owned())
+            .filter(|line| !line.is_empty())
+            .collect())
+    }
+
     /// Add an argument to pass to pkg-config.
     ///
     /// It's placed after all of the arguments generated by this library.
@@ -386,8 +415,14 @@ impl Config {
         }
         cmd.args(args).args(&self.extra_args);
 
+        let mut paths = self.search_paths.clone();
         if let Ok(value) = self.targetted_env_var("PKG_CONFIG_PATH") {
-            cmd.env("PKG_CONFIG_PATH", value);
+            paths.extend(env::split_paths(&value));
+        }
+        if !paths.is_empty() {
+            if let Ok(value) = env::join_paths(paths) {
+                cmd.env("PKG_CONFIG_PATH", value);
+            }
         }
         if let Ok(value) = self.targetted_env_var("PKG_CONFIG_LIBDIR") {
             cmd.env("PKG_CONFIG_LIBDIR", value);
@@ -447,6 +482,7 @@ impl Default for Config {
             min_version: Bound::Unbounded,
             max_version: Bound::Unbounded,
             extra_args: vec![],
+            search_paths: vec![],
             print_system_cflags: false,
             print_system_libs: false,
             cargo_metadata: false,
@@ -464,6 +500,7 @@ impl Library {
             frameworks: Vec::new(),
             framework_paths: Vec::new(),
             defines: HashMap::new(),
+            ld_args: Vec::new(),
             version: String::new(),
             _priv: (),
         }
@@ -534,6 +571,27 @@ impl Library {
                 self.frameworks.push(lib.to_string());
             }
         }
+
+        // Linker options passed through -Wl, minus the frameworks handled above,
+        // the name of a framework may follow in the next -Wl, word
+        let mut skip = false;
+        for option in words.iter().filter(|arg| arg.starts_with("-Wl,")) {
+            let mut ld_option = Vec::new();
+            for subopt in option[4..].split(',') {
+                if skip {
+                    skip = false;
+                    continue;
+                }
+                if subopt == "-framework" {
+                    skip = true;
+                    continue;
+                }
+                ld_option.push(subopt.to_string());
+            }
+            if !ld_option.is_empty() {
+                self.ld_args.push(ld_option);
+            }
+        }
     }
 
     fn parse_modversion(&mut self, output: &str) {
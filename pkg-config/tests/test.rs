@@ -136,6 +136,24 @@ fn framework() {
     assert!(lib.framework_paths.contains(&PathBuf::from("/usr/lib")));
 }
 
+#[test]
+fn ld_args() {
+    let _g = LOCK.lock();
+    reset();
+    let lib = find("rpath").unwrap();
+    assert_eq!(
+        lib.ld_args,
+        vec![
+            vec!["-rpath".to_string(), "/opt/rpath/lib".to_string()],
+            vec!["--as-needed".to_string()],
+        ]
+    );
+    assert!(lib.link_paths.contains(&PathBuf::from("/opt/rpath/lib")));
+
+    let lib = find("framework").unwrap();
+    assert!(lib.ld_args.is_empty());
+}
+
 #[test]
 fn get_variable() {
     let _g = LOCK.lock();
@@ -78,7 +78,10 @@ impl Flag {
 impl Arguments {
     ///constructs the parser
     pub fn new() -> Self {
-        let mut args: Vec<String> = std::env::args().skip(1).collect();
+        Self::from_args(std::env::args().skip(1).collect())
+    }
+    ///constructs the parser for args as they would be given on the command line, without the name of the program
+    pub fn from_args(mut args: Vec<String>) -> Self {
         let mut arglist = Vec::new();
         let mut flags = Vec::new();
         let option: String = args.remove(0);
@@ -170,6 +173,11 @@ impl Arguments {
         }
         None
     }
+    ///returns the argument following flag_name, used for short options that take a value such as -j 4
+    pub fn get_arg_value(&self, flag_name: &str) -> Option<Arg> {
+        let index = self.args.iter().position(|arg| arg.get_name() == flag_name)?;
+        self.args.get(index + 1).cloned()
+    }
     ///checks if a flag exists at the same level as a registered operation
     pub fn has_arg(&self, flag_name: &str) -> bool {
         self.get_arg(flag_name).is_some()
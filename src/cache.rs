@@ -0,0 +1,231 @@
+use crate::config::Config;
+use crate::upstream::{sha256, Index, Version, PROGRAM_DATA};
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File};
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
+use walkdir::WalkDir;
+///one archive kept in the cache
+#[derive(Debug, Clone)]
+pub struct CachedArchive {
+    name: String,
+    version: String,
+    checksum: String,
+    size: u64,
+    modified: SystemTime,
+    path: String,
+}
+impl CachedArchive {
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn get_version(&self) -> String {
+        self.version.clone()
+    }
+    pub fn get_checksum(&self) -> String {
+        self.checksum.clone()
+    }
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+    pub fn get_path(&self) -> String {
+        self.path.clone()
+    }
+}
+///downloaded archives kept as <name>/<version>/<sha256>.tar.gz and the last index read from each registry as index/<registry>.json
+///the directory is ~/.cppbuild/cache unless config.toml sets cache, so air-gapped machines can point it at a copied cache
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: String,
+}
+impl Cache {
+    pub fn new(dir: &str) -> Self {
+        Self { dir: dir.to_string() }
+    }
+    ///the cache configured in ~/.cppbuild/config.toml
+    pub fn open() -> io::Result<Self> {
+        match Config::load()?.get_cache() {
+            Some(dir) => Ok(Self::new(&dir)),
+            None => Ok(Self::new(&format!("{}/cache", PROGRAM_DATA.lock().unwrap()))),
+        }
+    }
+    pub fn get_dir(&self) -> String {
+        self.dir.clone()
+    }
+    fn archive_dir(&self, name: &str, version: &str) -> String {
+        format!("{}/{}/{}", self.dir, name, version)
+    }
+    ///the archive of name and version, it must have checksum when one is given
+    ///without a checksum the archive is only used when a single one of that version is cached
+    ///archives whose content no longer matches the checksum in their file name are ignored
+    pub fn find_archive(&self, name: &str, version: &str, checksum: Option<&str>) -> io::Result<Option<Vec<u8>>> {
+        let dir = self.archive_dir(name, version);
+        let checksums = match checksum {
+            Some(checksum) => vec![checksum.trim().to_lowercase()],
+            None => match std::fs::read_dir(&dir) {
+                Ok(entries) => entries
+                    .filter_map(|e| e.ok())
+                    .filter_map(|e| {
+                        let file_name = e.file_name().to_string_lossy().to_string();
+                        file_name.strip_suffix(".tar.gz").map(|checksum| checksum.to_string())
+                    })
+                    .collect(),
+                Err(_) => Vec::new(),
+            },
+        };
+        if checksums.len() > 1 {
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                format!(
+                    "{} archives of {} {} are cached: {}, choose one with checksum = \"<sha256>\" on its [[dependency]] in build.toml",
+                    checksums.len(),
+                    name,
+                    version,
+                    checksums.join(", ")
+                ),
+            ));
+        }
+        let checksum = match checksums.first() {
+            Some(checksum) => checksum,
+            None => return Ok(None),
+        };
+        let file = format!("{}/{}.tar.gz", dir, checksum);
+        if !Path::new(&file).exists() {
+            return Ok(None);
+        }
+        let mut data = Vec::new();
+        File::open(&file)?.read_to_end(&mut data)?;
+        if &sha256(&data) != checksum {
+            println!("warning: {} is corrupt, it is downloaded again", file);
+            return Ok(None);
+        }
+        Ok(Some(data))
+    }
+    ///keeps an archive that was verified against its checksum, the file is renamed into place so a reader never sees half of it
+    pub fn store_archive(&self, name: &str, version: &str, data: &[u8]) -> io::Result<String> {
+        let dir = self.archive_dir(name, version);
+        create_dir_all(&dir)?;
+        let file = format!("{}/{}.tar.gz", dir, sha256(data));
+        let tmp = format!("{}.{}", file, std::process::id());
+        File::create(&tmp)?.write_all(data)?;
+        std::fs::rename(&tmp, &file)?;
+        Ok(file)
+    }
+    ///remembers the index of registry so it can be resolved against with --offline
+    pub fn store_index(&self, registry: &str, index: &Index) -> io::Result<()> {
+        create_dir_all(format!("{}/index", self.dir))?;
+        let json = match serde_json::to_string_pretty(index) {
+            Ok(json) => json,
+            Err(e) => return Err(io::Error::new(ErrorKind::Other, e)),
+        };
+        let file = format!("{}/index/{}.json", self.dir, registry);
+        let tmp = format!("{}.{}", file, std::process::id());
+        File::create(&tmp)?.write_all(json.as_bytes())?;
+        std::fs::rename(&tmp, &file)
+    }
+    ///the index of registry as it was when it was last read
+    pub fn load_index(&self, registry: &str) -> io::Result<Index> {
+        let file = format!("{}/index/{}.json", self.dir, registry);
+        let mut content = String::new();
+        match File::open(&file) {
+            Ok(mut f) => f.read_to_string(&mut content)?,
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("the index of {} is not cached in {}", registry, self.dir),
+                ))
+            }
+        };
+        let mut index: Index = match serde_json::from_str(&content) {
+            Ok(index) => index,
+            Err(e) => return Err(io::Error::new(ErrorKind::InvalidData, format!("{}: {}", file, e))),
+        };
+        index.set_registry(registry);
+        Ok(index)
+    }
+    ///every cached archive sorted by name and version
+    pub fn list(&self) -> io::Result<Vec<CachedArchive>> {
+        let mut archives = Vec::new();
+        for entry in WalkDir::new(&self.dir).min_depth(3).max_depth(3).into_iter().filter_map(|e| e.ok()) {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let checksum = match file_name.strip_suffix(".tar.gz") {
+                Some(checksum) => checksum.to_string(),
+                None => continue,
+            };
+            let version_dir = match entry.path().parent() {
+                Some(dir) => dir,
+                None => continue,
+            };
+            let name_dir = match version_dir.parent() {
+                Some(dir) => dir,
+                None => continue,
+            };
+            let metadata = entry.metadata().map_err(|e| io::Error::new(ErrorKind::Other, e))?;
+            archives.push(CachedArchive {
+                name: file_name_of(name_dir),
+                version: file_name_of(version_dir),
+                checksum,
+                size: metadata.len(),
+                modified: metadata.modified()?,
+                path: format!("{}", entry.path().display()),
+            });
+        }
+        archives.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        Ok(archives)
+    }
+    ///removes everything in the cache, archives and indexes
+    pub fn clean(&self) -> io::Result<()> {
+        if Path::new(&self.dir).exists() {
+            std::fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(())
+    }
+    ///keeps the newest keep versions of each package and the most recently downloaded archive of each of those versions
+    ///returns the archives that were removed
+    pub fn prune(&self, keep: usize) -> io::Result<Vec<CachedArchive>> {
+        let mut packages: HashMap<String, Vec<CachedArchive>> = HashMap::new();
+        for archive in self.list()? {
+            packages.entry(archive.name.clone()).or_insert_with(Vec::new).push(archive);
+        }
+        let mut removed = Vec::new();
+        for (_, mut archives) in packages.into_iter() {
+            //newest version first, then the most recent download of the same version
+            archives.sort_by(|a, b| {
+                compare_versions(&b.version, &a.version).then_with(|| b.modified.cmp(&a.modified))
+            });
+            let mut versions: Vec<String> = Vec::new();
+            for archive in archives.into_iter() {
+                let duplicate = versions.contains(&archive.version);
+                if !duplicate && versions.len() < keep {
+                    versions.push(archive.version.clone());
+                    continue;
+                }
+                std::fs::remove_file(&archive.path)?;
+                if let Some(dir) = Path::new(&archive.path).parent() {
+                    //only succeeds once the version has no archives left
+                    let _ = std::fs::remove_dir(dir);
+                }
+                removed.push(archive);
+            }
+        }
+        removed.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        Ok(removed)
+    }
+}
+fn file_name_of(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => String::new(),
+    }
+}
+///orders version directories by version, names that are not versions sort before every version
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    match (Version::parse(a), Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        (Ok(_), Err(_)) => std::cmp::Ordering::Greater,
+        (Err(_), Ok(_)) => std::cmp::Ordering::Less,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
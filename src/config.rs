@@ -0,0 +1,47 @@
+use crate::upstream::PROGRAM_DATA;
+use serde_derive::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+///~/.cppbuild/config.toml, settings shared by every project of the user
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Config {
+    registry: Option<Vec<RegistryConfig>>,
+    ///where downloaded archives are kept, ~/.cppbuild/cache by default
+    cache: Option<String>,
+}
+///a [[registry]] table, url is either an http(s) url or a directory such as a shared nfs mount
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RegistryConfig {
+    pub name: String,
+    pub url: String,
+}
+impl Config {
+    pub fn path() -> String {
+        format!("{}/config.toml", PROGRAM_DATA.lock().unwrap())
+    }
+    ///reads ~/.cppbuild/config.toml, a missing file gives the default settings
+    pub fn load() -> io::Result<Self> {
+        let path = Self::path();
+        if !Path::new(&path).exists() {
+            return Ok(Self::default());
+        }
+        let mut content = String::new();
+        File::open(&path)?.read_to_string(&mut content)?;
+        match toml::from_str(&content) {
+            Ok(config) => Ok(config),
+            Err(e) => Err(io::Error::new(ErrorKind::InvalidData, format!("{}: {}", path, e))),
+        }
+    }
+    ///the registries in the order they are listed, earlier registries win when several publish the same version
+    pub fn get_registries(&self) -> Vec<RegistryConfig> {
+        match &self.registry {
+            Some(registries) => registries.clone(),
+            None => Vec::new(),
+        }
+    }
+    pub fn get_cache(&self) -> Option<String> {
+        self.cache.clone()
+    }
+}
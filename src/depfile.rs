@@ -0,0 +1,140 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::SystemTime;
+///the files an object was built from, as recorded by the compiler when it is passed -MMD -MF
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepFile {
+    targets: Vec<String>,
+    dependencies: Vec<String>,
+}
+impl DepFile {
+    pub fn from_file(path: &str) -> std::io::Result<Self> {
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+        Self::parse(&content)
+    }
+    ///parses the make rule written by g++ and clang++, lines may be continued with \ and spaces in paths are escaped with \
+    pub fn parse(content: &str) -> std::io::Result<Self> {
+        let mut words: Vec<String> = Vec::new();
+        let mut word = String::new();
+        let mut chars = content.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.peek() {
+                    Some('\n') | Some('\r') => {
+                        while let Some('\n') | Some('\r') = chars.peek() {
+                            chars.next();
+                        }
+                        flush_word(&mut words, &mut word);
+                    }
+                    Some(' ') | Some('#') => word.push(chars.next().unwrap()),
+                    _ => word.push(c),
+                },
+                '$' if chars.peek() == Some(&'$') => {
+                    chars.next();
+                    word.push('$');
+                }
+                ' ' | '\t' => flush_word(&mut words, &mut word),
+                '\r' | '\n' => {
+                    flush_word(&mut words, &mut word);
+                    //only the first rule is needed, later ones are phony targets for headers
+                    if !words.is_empty() {
+                        break;
+                    }
+                }
+                _ => word.push(c),
+            }
+        }
+        flush_word(&mut words, &mut word);
+        let colon = match words.iter().position(|w| w.ends_with(':')) {
+            Some(index) => index,
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "dependency file has no target",
+                ))
+            }
+        };
+        //the colon ends the last target, or stands on its own after a space
+        let mut targets = words[..=colon].to_vec();
+        if let Some(last) = targets.last_mut() {
+            last.pop();
+        }
+        targets.retain(|target| !target.is_empty());
+        Ok(Self {
+            targets,
+            dependencies: words[colon + 1..].to_vec(),
+        })
+    }
+    ///the objects the rule is for, usually just one
+    pub fn get_targets(&self) -> &[String] {
+        self.targets.as_slice()
+    }
+    ///every file the target was compiled from, the source file itself comes first
+    pub fn get_dependencies(&self) -> &[String] {
+        self.dependencies.as_slice()
+    }
+    ///checks if any recorded file was modified after time, files that no longer exist count as modified
+    pub fn is_newer_than(&self, time: SystemTime) -> bool {
+        for dependency in self.dependencies.iter() {
+            match std::fs::metadata(Path::new(dependency)).and_then(|m| m.modified()) {
+                Ok(modified) if modified <= time => (),
+                _ => return true,
+            }
+        }
+        false
+    }
+}
+fn flush_word(words: &mut Vec<String>, word: &mut String) {
+    if !word.is_empty() {
+        words.push(word.clone());
+        word.clear();
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::DepFile;
+    #[test]
+    fn single_line() {
+        let deps = DepFile::parse("obj/main.o: src/main.cpp headers/a.h\n").unwrap();
+        assert_eq!(deps.get_targets(), ["obj/main.o"]);
+        assert_eq!(deps.get_dependencies(), ["src/main.cpp", "headers/a.h"]);
+    }
+    #[test]
+    fn continuation_lines() {
+        let deps = DepFile::parse("main.o: main.cpp \\\n  a.h \\\r\n  b.h\n").unwrap();
+        assert_eq!(deps.get_dependencies(), ["main.cpp", "a.h", "b.h"]);
+    }
+    #[test]
+    fn escaped_spaces_and_hashes() {
+        let deps = DepFile::parse("my\\ obj.o: my\\ dir/main.cpp issue\\#1.h\n").unwrap();
+        assert_eq!(deps.get_targets(), ["my obj.o"]);
+        assert_eq!(deps.get_dependencies(), ["my dir/main.cpp", "issue#1.h"]);
+    }
+    #[test]
+    fn escaped_dollar() {
+        let deps = DepFile::parse("main.o: main.cpp price$$list.h\n").unwrap();
+        assert_eq!(deps.get_dependencies(), ["main.cpp", "price$list.h"]);
+    }
+    #[test]
+    fn multiple_targets() {
+        let deps = DepFile::parse("main.o main.d : main.cpp a.h\n").unwrap();
+        assert_eq!(deps.get_targets(), ["main.o", "main.d"]);
+        assert_eq!(deps.get_dependencies(), ["main.cpp", "a.h"]);
+    }
+    #[test]
+    fn phony_header_rules_are_skipped() {
+        let deps = DepFile::parse("main.o: main.cpp a.h\n\na.h:\n").unwrap();
+        assert_eq!(deps.get_dependencies(), ["main.cpp", "a.h"]);
+    }
+    #[test]
+    fn windows_backslashes_are_kept() {
+        let deps = DepFile::parse("main.o: src\\main.cpp\n").unwrap();
+        assert_eq!(deps.get_dependencies(), ["src\\main.cpp"]);
+    }
+    #[test]
+    fn missing_target() {
+        assert!(DepFile::parse("main.cpp a.h\n").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+// assertions for ///test functions, every build puts this header on the include path because tests/ is always compiled
+// a failed assertion throws cppbuild_test::Failure, the harness catches it and reports the file, line,
+// expression and values as the reason the test failed
+// every macro is also available without the CPPBUILD_ prefix unless CPPBUILD_NO_SHORT_ASSERTS is defined
+#ifndef CPPBUILD_TEST_H
+#define CPPBUILD_TEST_H
+#include <cmath>
+#include <limits>
+#include <sstream>
+#include <string>
+#include <type_traits>
+#include <utility>
+namespace cppbuild_test {
+// not a std::exception so a test catching std::exception does not swallow a failed assertion
+struct Failure {
+    std::string message;
+};
+template <typename T>
+class is_printable {
+    template <typename U>
+    static auto check(int) -> decltype(std::declval<std::ostream&>() << std::declval<const U&>(), std::true_type());
+    template <typename>
+    static std::false_type check(...);
+
+public:
+    static const bool value = decltype(check<T>(0))::value;
+};
+template <typename T>
+typename std::enable_if<is_printable<T>::value && !std::is_floating_point<T>::value, std::string>::type to_string(
+    const T& value) {
+    std::ostringstream out;
+    out << value;
+    return out.str();
+}
+// the fewest digits that read back as the same value, so 0.30000000000000004 does not print as 0.3
+template <typename T>
+typename std::enable_if<std::is_floating_point<T>::value, std::string>::type to_string(const T& value) {
+    std::ostringstream out;
+    for (int digits = std::numeric_limits<T>::digits10; digits <= std::numeric_limits<T>::max_digits10; digits++) {
+        out.str("");
+        out.precision(digits);
+        out << value;
+        std::istringstream in(out.str());
+        T parsed;
+        if (in >> parsed && parsed == value) {
+            break;
+        }
+    }
+    return out.str();
+}
+template <typename T>
+typename std::enable_if<!is_printable<T>::value, std::string>::type to_string(const T&) {
+    return "<unprintable>";
+}
+inline std::string to_string(const std::string& value) {
+    return "\"" + value + "\"";
+}
+inline std::string to_string(const char* value) {
+    return value == nullptr ? "nullptr" : "\"" + std::string(value) + "\"";
+}
+inline std::string to_string(bool value) {
+    return value ? "true" : "false";
+}
+inline std::string location(const char* file, int line) {
+    return std::string(file) + ":" + std::to_string(line);
+}
+[[noreturn]] inline void fail(const char* file, int line, const std::string& message) {
+    throw Failure{"assertion failed at " + location(file, line) + ": " + message};
+}
+inline void check(bool passed, const char* file, int line, const char* expression) {
+    if (!passed) {
+        fail(file, line, expression);
+    }
+}
+#define CPPBUILD_COMPARISON(name, op) \
+    struct name { \
+        template <typename L, typename R> \
+        bool operator()(const L& left, const R& right) const { \
+            return static_cast<bool>(left op right); \
+        } \
+    };
+CPPBUILD_COMPARISON(Equal, ==)
+CPPBUILD_COMPARISON(NotEqual, !=)
+CPPBUILD_COMPARISON(Less, <)
+CPPBUILD_COMPARISON(LessEqual, <=)
+CPPBUILD_COMPARISON(Greater, >)
+CPPBUILD_COMPARISON(GreaterEqual, >=)
+#undef CPPBUILD_COMPARISON
+// fails with both values when compare does not hold between left and right
+template <typename L, typename R, typename Compare>
+void compare(
+    const L& left,
+    const R& right,
+    Compare compare,
+    const char* file,
+    int line,
+    const char* macro,
+    const char* left_expression,
+    const char* right_expression) {
+    if (!compare(left, right)) {
+        fail(
+            file,
+            line,
+            std::string(macro) + "(" + left_expression + ", " + right_expression + ")\n  left: " + to_string(left) +
+                "\n right: " + to_string(right));
+    }
+}
+template <typename L, typename R, typename T>
+void near(
+    const L& left,
+    const R& right,
+    const T& tolerance,
+    const char* file,
+    int line,
+    const char* left_expression,
+    const char* right_expression,
+    const char* tolerance_expression) {
+    if (!(std::fabs(left - right) <= tolerance)) {
+        fail(
+            file,
+            line,
+            std::string("ASSERT_NEAR(") + left_expression + ", " + right_expression + ", " + tolerance_expression +
+                ")\n  left: " + to_string(left) + "\n right: " + to_string(right) + "\n  diff: " +
+                to_string(std::fabs(left - right)) + " > " + to_string(tolerance));
+    }
+}
+}
+#define CPPBUILD_ASSERT(expression) \
+    ::cppbuild_test::check(static_cast<bool>(expression), __FILE__, __LINE__, "ASSERT(" #expression ") is false")
+#define CPPBUILD_ASSERT_FALSE(expression) \
+    ::cppbuild_test::check(!static_cast<bool>(expression), __FILE__, __LINE__, "ASSERT_FALSE(" #expression ") is true")
+#define CPPBUILD_ASSERT_CMP(macro, compare_with, left, right) \
+    ::cppbuild_test::compare( \
+        (left), (right), ::cppbuild_test::compare_with(), __FILE__, __LINE__, macro, #left, #right)
+#define CPPBUILD_ASSERT_EQ(left, right) CPPBUILD_ASSERT_CMP("ASSERT_EQ", Equal, left, right)
+#define CPPBUILD_ASSERT_NE(left, right) CPPBUILD_ASSERT_CMP("ASSERT_NE", NotEqual, left, right)
+#define CPPBUILD_ASSERT_LT(left, right) CPPBUILD_ASSERT_CMP("ASSERT_LT", Less, left, right)
+#define CPPBUILD_ASSERT_LE(left, right) CPPBUILD_ASSERT_CMP("ASSERT_LE", LessEqual, left, right)
+#define CPPBUILD_ASSERT_GT(left, right) CPPBUILD_ASSERT_CMP("ASSERT_GT", Greater, left, right)
+#define CPPBUILD_ASSERT_GE(left, right) CPPBUILD_ASSERT_CMP("ASSERT_GE", GreaterEqual, left, right)
+#define CPPBUILD_ASSERT_NEAR(left, right, tolerance) \
+    ::cppbuild_test::near((left), (right), (tolerance), __FILE__, __LINE__, #left, #right, #tolerance)
+// passes when statement throws exception, any other exception or none at all fails
+#define CPPBUILD_ASSERT_THROWS(statement, exception) \
+    do { \
+        bool cppbuild_thrown = false; \
+        try { \
+            statement; \
+        } catch (const exception&) { \
+            cppbuild_thrown = true; \
+        } catch (const ::cppbuild_test::Failure&) { \
+            throw; \
+        } catch (...) { \
+            ::cppbuild_test::fail( \
+                __FILE__, __LINE__, "ASSERT_THROWS(" #statement ", " #exception ") threw a different exception"); \
+        } \
+        if (!cppbuild_thrown) { \
+            ::cppbuild_test::fail(__FILE__, __LINE__, "ASSERT_THROWS(" #statement ", " #exception ") did not throw"); \
+        } \
+    } while (false)
+#define CPPBUILD_ASSERT_NO_THROW(statement) \
+    do { \
+        try { \
+            statement; \
+        } catch (const ::cppbuild_test::Failure&) { \
+            throw; \
+        } catch (...) { \
+            ::cppbuild_test::fail(__FILE__, __LINE__, "ASSERT_NO_THROW(" #statement ") threw an exception"); \
+        } \
+    } while (false)
+#define CPPBUILD_FAIL(message) ::cppbuild_test::fail(__FILE__, __LINE__, (message))
+#ifndef CPPBUILD_NO_SHORT_ASSERTS
+#define ASSERT_TRUE(expression) CPPBUILD_ASSERT(expression)
+#define ASSERT_FALSE(expression) CPPBUILD_ASSERT_FALSE(expression)
+#define ASSERT_EQ(left, right) CPPBUILD_ASSERT_EQ(left, right)
+#define ASSERT_NE(left, right) CPPBUILD_ASSERT_NE(left, right)
+#define ASSERT_LT(left, right) CPPBUILD_ASSERT_LT(left, right)
+#define ASSERT_LE(left, right) CPPBUILD_ASSERT_LE(left, right)
+#define ASSERT_GT(left, right) CPPBUILD_ASSERT_GT(left, right)
+#define ASSERT_GE(left, right) CPPBUILD_ASSERT_GE(left, right)
+#define ASSERT_NEAR(left, right, tolerance) CPPBUILD_ASSERT_NEAR(left, right, tolerance)
+#define ASSERT_THROWS(statement, exception) CPPBUILD_ASSERT_THROWS(statement, exception)
+#define ASSERT_NO_THROW(statement) CPPBUILD_ASSERT_NO_THROW(statement)
+#define FAIL(message) CPPBUILD_FAIL(message)
+#endif
+#endif
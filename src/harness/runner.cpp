@@ -0,0 +1,477 @@
+// the runner of the test harness cppbuild generates in target/test_<name>.cpp
+// the generated file declares every ///test function, wraps it in a function returning why it failed
+// and lists the wrappers in cppbuild_harness::collect_tests after this file
+// every test runs in a child process so a crash or a hang only fails that test
+// --format junit, tap or json writes a report with the duration, output and failure of each test to --output or stdout
+#include "cppbuild_test.h"
+#include <cerrno>
+#include <chrono>
+#include <cstdio>
+#include <cstdlib>
+#include <cstring>
+#include <ctime>
+#include <exception>
+#include <string>
+#include <utility>
+#include <vector>
+#include <signal.h>
+#include <sys/wait.h>
+#include <unistd.h>
+namespace cppbuild_harness {
+struct TestCase {
+    const char* name;
+    // returns an empty string when the test passed, otherwise why it failed
+    std::string (*run)();
+    // seconds the test may run, 0 for the timeout given with --test-timeout
+    double timeout;
+    // marked ///test ignore, only run with --include-ignored
+    bool ignored;
+};
+std::vector<TestCase> collect_tests();
+// runs one test, a failed assertion or an exception escaping the test is a failure
+std::string run_test(const TestCase& test) {
+    try {
+        return test.run();
+    } catch (const cppbuild_test::Failure& failure) {
+        return failure.message;
+    } catch (const std::exception& e) {
+        return std::string("threw an exception: ") + e.what();
+    } catch (...) {
+        return "threw an exception";
+    }
+}
+const char* signal_name(int signal) {
+    switch (signal) {
+    case SIGSEGV: return "SIGSEGV";
+    case SIGABRT: return "SIGABRT";
+    case SIGFPE: return "SIGFPE";
+    case SIGBUS: return "SIGBUS";
+    case SIGILL: return "SIGILL";
+    case SIGKILL: return "SIGKILL";
+    case SIGTERM: return "SIGTERM";
+    case SIGPIPE: return "SIGPIPE";
+    default: return "an unknown signal";
+    }
+}
+// what happened when a test ran, failure is empty when it passed
+struct Outcome {
+    std::string failure;
+    double seconds = 0;
+    // what the test printed, only kept when output is captured
+    std::string out;
+    std::string err;
+};
+// reads back everything written to a file made by tmpfile
+std::string read_all(FILE* file) {
+    std::string content;
+    if (file == nullptr) {
+        return content;
+    }
+    std::rewind(file);
+    char buffer[4096];
+    std::size_t count;
+    while ((count = std::fread(buffer, 1, sizeof(buffer), file)) > 0) {
+        content.append(buffer, count);
+    }
+    std::fclose(file);
+    return content;
+}
+std::string wait_for(pid_t pid, int pipe_fd, double timeout) {
+    auto deadline = std::chrono::steady_clock::now() + std::chrono::duration<double>(timeout);
+    int status = 0;
+    bool timed_out = false;
+    while (waitpid(pid, &status, WNOHANG) == 0) {
+        if (timeout > 0 && std::chrono::steady_clock::now() > deadline) {
+            kill(pid, SIGKILL);
+            waitpid(pid, &status, 0);
+            timed_out = true;
+            break;
+        }
+        usleep(1000);
+    }
+    std::string failure;
+    char buffer[512];
+    ssize_t count;
+    while ((count = read(pipe_fd, buffer, sizeof(buffer))) > 0) {
+        failure.append(buffer, count);
+    }
+    close(pipe_fd);
+    if (timed_out) {
+        char message[64];
+        std::snprintf(message, sizeof(message), "timed out after %gs", timeout);
+        return message;
+    }
+    if (WIFSIGNALED(status)) {
+        int signal = WTERMSIG(status);
+        return std::string("killed by ") + signal_name(signal) + " (" + strsignal(signal) + ")";
+    }
+    if (failure.empty() && WIFEXITED(status) && WEXITSTATUS(status) != 0) {
+        return "exited with status " + std::to_string(WEXITSTATUS(status));
+    }
+    return failure;
+}
+// runs the test in a forked child and waits for it at most timeout seconds
+// the child sends why it failed through a pipe, a child that dies without sending anything crashed
+// with capture the stdout and stderr of the child go to temporary files instead of the terminal
+Outcome run_isolated(const TestCase& test, double timeout, bool capture) {
+    Outcome outcome;
+    int pipe_fds[2];
+    if (pipe(pipe_fds) != 0) {
+        outcome.failure = std::string("unable to create a pipe: ") + std::strerror(errno);
+        return outcome;
+    }
+    FILE* out = capture ? std::tmpfile() : nullptr;
+    FILE* err = capture ? std::tmpfile() : nullptr;
+    std::fflush(nullptr);
+    auto start = std::chrono::steady_clock::now();
+    pid_t pid = fork();
+    if (pid < 0) {
+        outcome.failure = std::string("unable to fork: ") + std::strerror(errno);
+        return outcome;
+    }
+    if (pid == 0) {
+        close(pipe_fds[0]);
+        if (out != nullptr && err != nullptr) {
+            dup2(fileno(out), STDOUT_FILENO);
+            dup2(fileno(err), STDERR_FILENO);
+            // unbuffered so the output of a test that crashes is not lost
+            std::setvbuf(stdout, nullptr, _IONBF, 0);
+        }
+        std::string failure = run_test(test);
+        // the message has to fit in the pipe, the parent only reads it once the child is gone
+        if (failure.size() > 4096) {
+            failure.resize(4096);
+        }
+        if (!failure.empty() && write(pipe_fds[1], failure.data(), failure.size()) < 0) {
+            failure.clear();
+        }
+        close(pipe_fds[1]);
+        std::fflush(nullptr);
+        _exit(failure.empty() ? 0 : 1);
+    }
+    close(pipe_fds[1]);
+    outcome.failure = wait_for(pid, pipe_fds[0], timeout);
+    outcome.seconds = std::chrono::duration<double>(std::chrono::steady_clock::now() - start).count();
+    outcome.out = read_all(out);
+    outcome.err = read_all(err);
+    return outcome;
+}
+// the arguments cppbuild test passes on to the harness
+struct Options {
+    // only tests whose name contains filter run, or whose name is filter with --exact
+    std::string filter;
+    bool exact = false;
+    bool list = false;
+    bool include_ignored = false;
+    // the timeout of --test-timeout <seconds>
+    double timeout = 60;
+    // human, junit, tap or json, set with --format
+    std::string format = "human";
+    // where the report of --format goes, stdout without --output
+    std::string output;
+};
+Options parse_options(int argc, char** argv) {
+    Options options;
+    for (int i = 1; i < argc; i++) {
+        std::string arg = argv[i];
+        if (arg == "--test-timeout" && i + 1 < argc) {
+            options.timeout = std::atof(argv[++i]);
+        } else if (arg == "--format" && i + 1 < argc) {
+            options.format = argv[++i];
+        } else if (arg == "--output" && i + 1 < argc) {
+            options.output = argv[++i];
+        } else if (arg == "--exact") {
+            options.exact = true;
+        } else if (arg == "--list") {
+            options.list = true;
+        } else if (arg == "--include-ignored") {
+            options.include_ignored = true;
+        } else {
+            options.filter = arg;
+        }
+    }
+    return options;
+}
+bool matches(const Options& options, const TestCase& test) {
+    if (options.exact) {
+        return options.filter.empty() || options.filter == test.name;
+    }
+    return std::string(test.name).find(options.filter) != std::string::npos;
+}
+// one test as it ends up in the report
+struct Result {
+    std::string name;
+    bool ignored;
+    Outcome outcome;
+};
+struct Summary {
+    std::string suite;
+    std::vector<Result> results;
+    std::size_t passed = 0;
+    std::size_t failed = 0;
+    std::size_t ignored = 0;
+    std::size_t filtered_out = 0;
+    double seconds = 0;
+    std::time_t started = 0;
+};
+std::string xml_escape(const std::string& text) {
+    std::string escaped;
+    for (char c : text) {
+        switch (c) {
+        case '&': escaped += "&amp;"; break;
+        case '<': escaped += "&lt;"; break;
+        case '>': escaped += "&gt;"; break;
+        case '"': escaped += "&quot;"; break;
+        case '\'': escaped += "&apos;"; break;
+        default:
+            // xml 1.0 has no way to write other control characters
+            if (static_cast<unsigned char>(c) < 0x20 && c != '\n' && c != '\r' && c != '\t') {
+                escaped += '?';
+            } else {
+                escaped += c;
+            }
+        }
+    }
+    return escaped;
+}
+std::string json_escape(const std::string& text) {
+    std::string escaped = "\"";
+    for (char c : text) {
+        switch (c) {
+        case '"': escaped += "\\\""; break;
+        case '\\': escaped += "\\\\"; break;
+        case '\n': escaped += "\\n"; break;
+        case '\r': escaped += "\\r"; break;
+        case '\t': escaped += "\\t"; break;
+        default:
+            if (static_cast<unsigned char>(c) < 0x20) {
+                char code[8];
+                std::snprintf(code, sizeof(code), "\\u%04x", c);
+                escaped += code;
+            } else {
+                escaped += c;
+            }
+        }
+    }
+    return escaped + "\"";
+}
+std::string first_line(const std::string& text) {
+    return text.substr(0, text.find('\n'));
+}
+void write_junit(FILE* file, const Summary& summary) {
+    char timestamp[32];
+    std::strftime(timestamp, sizeof(timestamp), "%Y-%m-%dT%H:%M:%SZ", std::gmtime(&summary.started));
+    std::string suite = xml_escape(summary.suite);
+    std::fprintf(file, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    std::fprintf(
+        file,
+        "<testsuites name=\"%s\" tests=\"%zu\" failures=\"%zu\" skipped=\"%zu\" time=\"%.3f\">\n",
+        suite.c_str(),
+        summary.results.size(),
+        summary.failed,
+        summary.ignored,
+        summary.seconds);
+    std::fprintf(
+        file,
+        "  <testsuite name=\"%s\" tests=\"%zu\" failures=\"%zu\" errors=\"0\" skipped=\"%zu\" time=\"%.3f\" timestamp=\"%s\">\n",
+        suite.c_str(),
+        summary.results.size(),
+        summary.failed,
+        summary.ignored,
+        summary.seconds,
+        timestamp);
+    for (const Result& result : summary.results) {
+        std::fprintf(
+            file,
+            "    <testcase name=\"%s\" classname=\"%s\" time=\"%.3f\">\n",
+            xml_escape(result.name).c_str(),
+            suite.c_str(),
+            result.outcome.seconds);
+        if (result.ignored) {
+            std::fprintf(file, "      <skipped/>\n");
+        } else if (!result.outcome.failure.empty()) {
+            std::fprintf(
+                file,
+                "      <failure message=\"%s\">%s</failure>\n",
+                xml_escape(first_line(result.outcome.failure)).c_str(),
+                xml_escape(result.outcome.failure).c_str());
+        }
+        if (!result.outcome.out.empty()) {
+            std::fprintf(file, "      <system-out>%s</system-out>\n", xml_escape(result.outcome.out).c_str());
+        }
+        if (!result.outcome.err.empty()) {
+            std::fprintf(file, "      <system-err>%s</system-err>\n", xml_escape(result.outcome.err).c_str());
+        }
+        std::fprintf(file, "    </testcase>\n");
+    }
+    std::fprintf(file, "  </testsuite>\n</testsuites>\n");
+}
+// a yaml block scalar indented under a key of the tap diagnostics
+void write_yaml_block(FILE* file, const char* key, const std::string& text) {
+    if (text.empty()) {
+        return;
+    }
+    std::fprintf(file, "  %s: |-\n", key);
+    std::size_t start = 0;
+    while (start <= text.size()) {
+        std::size_t end = text.find('\n', start);
+        if (end == std::string::npos) {
+            end = text.size();
+        }
+        // a trailing newline does not start another line
+        if (start == text.size() && start != 0) {
+            break;
+        }
+        std::fprintf(file, "    %s\n", text.substr(start, end - start).c_str());
+        start = end + 1;
+    }
+}
+void write_tap(FILE* file, const Summary& summary) {
+    std::fprintf(file, "TAP version 13\n1..%zu\n", summary.results.size());
+    std::size_t number = 0;
+    for (const Result& result : summary.results) {
+        number++;
+        if (result.ignored) {
+            std::fprintf(file, "ok %zu - %s # SKIP ignored\n", number, result.name.c_str());
+            continue;
+        }
+        bool passed = result.outcome.failure.empty();
+        std::fprintf(file, "%s %zu - %s\n", passed ? "ok" : "not ok", number, result.name.c_str());
+        std::fprintf(file, "  ---\n  duration_ms: %.3f\n", result.outcome.seconds * 1000);
+        write_yaml_block(file, "message", result.outcome.failure);
+        write_yaml_block(file, "stdout", result.outcome.out);
+        write_yaml_block(file, "stderr", result.outcome.err);
+        std::fprintf(file, "  ...\n");
+    }
+}
+void write_json(FILE* file, const Summary& summary) {
+    std::fprintf(file, "{\n  \"name\": %s,\n  \"tests\": [\n", json_escape(summary.suite).c_str());
+    for (std::size_t i = 0; i < summary.results.size(); i++) {
+        const Result& result = summary.results[i];
+        const char* status = result.ignored ? "ignored" : result.outcome.failure.empty() ? "passed" : "failed";
+        std::fprintf(
+            file,
+            "    {\"name\": %s, \"status\": \"%s\", \"duration\": %.6f, \"message\": %s, \"stdout\": %s, \"stderr\": %s}%s\n",
+            json_escape(result.name).c_str(),
+            status,
+            result.outcome.seconds,
+            json_escape(result.outcome.failure).c_str(),
+            json_escape(result.outcome.out).c_str(),
+            json_escape(result.outcome.err).c_str(),
+            i + 1 < summary.results.size() ? "," : "");
+    }
+    std::fprintf(
+        file,
+        "  ],\n  \"passed\": %zu,\n  \"failed\": %zu,\n  \"ignored\": %zu,\n  \"filtered_out\": %zu,\n  \"duration\": %.6f\n}\n",
+        summary.passed,
+        summary.failed,
+        summary.ignored,
+        summary.filtered_out,
+        summary.seconds);
+}
+// prints what a test wrote to stdout or stderr after its failure message
+void print_captured(const char* name, const char* stream, const std::string& text) {
+    if (!text.empty()) {
+        std::printf("\n---- %s %s ----\n%s%s", name, stream, text.c_str(), text.back() == '\n' ? "" : "\n");
+    }
+}
+}
+int main(int argc, char** argv) {
+    using namespace cppbuild_harness;
+    Options options = parse_options(argc, argv);
+    if (options.format != "human" && options.format != "junit" && options.format != "tap" && options.format != "json") {
+        std::fprintf(stderr, "error: unknown format %s, expected human, junit, tap or json\n", options.format.c_str());
+        return 1;
+    }
+    Summary summary;
+    // the executable is target/<profile>/tests/<name>
+    summary.suite = argv[0];
+    summary.suite = summary.suite.substr(summary.suite.find_last_of('/') + 1);
+    std::vector<TestCase> tests;
+    for (const TestCase& test : collect_tests()) {
+        if (matches(options, test)) {
+            tests.push_back(test);
+        } else {
+            summary.filtered_out++;
+        }
+    }
+    if (options.list) {
+        for (const TestCase& test : tests) {
+            std::printf("%s: test%s\n", test.name, test.ignored ? " (ignored)" : "");
+        }
+        std::printf("\n%zu test%s\n", tests.size(), tests.size() == 1 ? "" : "s");
+        return 0;
+    }
+    // a report on stdout replaces the usual output, the output of the tests is captured so it does not end up in the report
+    bool report = options.format != "human";
+    bool human = !report || !options.output.empty();
+    FILE* output = stdout;
+    if (report && !options.output.empty()) {
+        output = std::fopen(options.output.c_str(), "w");
+        if (output == nullptr) {
+            std::fprintf(stderr, "error: unable to write %s: %s\n", options.output.c_str(), std::strerror(errno));
+            return 1;
+        }
+    }
+    auto start = std::chrono::steady_clock::now();
+    summary.started = std::time(nullptr);
+    if (human) {
+        std::printf("\nrunning %zu test%s\n", tests.size(), tests.size() == 1 ? "" : "s");
+    }
+    for (const TestCase& test : tests) {
+        Result result{test.name, test.ignored && !options.include_ignored, Outcome()};
+        if (result.ignored) {
+            summary.ignored++;
+        } else {
+            result.outcome = run_isolated(test, test.timeout > 0 ? test.timeout : options.timeout, report);
+            if (result.outcome.failure.empty()) {
+                summary.passed++;
+            } else {
+                summary.failed++;
+            }
+        }
+        if (human) {
+            const char* status = result.ignored ? "ignored" : result.outcome.failure.empty() ? "ok" : "FAILED";
+            std::printf("test %s ... %s\n", test.name, status);
+        }
+        summary.results.push_back(result);
+    }
+    summary.seconds = std::chrono::duration<double>(std::chrono::steady_clock::now() - start).count();
+    if (human && summary.failed > 0) {
+        std::printf("\nfailures:\n");
+        for (const Result& result : summary.results) {
+            if (!result.outcome.failure.empty()) {
+                std::printf("\n---- %s ----\n%s\n", result.name.c_str(), result.outcome.failure.c_str());
+                print_captured(result.name.c_str(), "stdout", result.outcome.out);
+                print_captured(result.name.c_str(), "stderr", result.outcome.err);
+            }
+        }
+        std::printf("\nfailures:\n");
+        for (const Result& result : summary.results) {
+            if (!result.outcome.failure.empty()) {
+                std::printf("    %s\n", result.name.c_str());
+            }
+        }
+    }
+    if (human) {
+        std::printf(
+            "\ntest result: %s. %zu passed; %zu failed; %zu ignored; %zu filtered out; finished in %.2fs\n\n",
+            summary.failed == 0 ? "ok" : "FAILED",
+            summary.passed,
+            summary.failed,
+            summary.ignored,
+            summary.filtered_out,
+            summary.seconds);
+    }
+    if (options.format == "junit") {
+        write_junit(output, summary);
+    } else if (options.format == "tap") {
+        write_tap(output, summary);
+    } else if (options.format == "json") {
+        write_json(output, summary);
+    }
+    if (output != stdout) {
+        std::fclose(output);
+    }
+    return summary.failed == 0 ? 0 : 101;
+}
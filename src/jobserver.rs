@@ -0,0 +1,86 @@
+use std::io::Write;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+///a single compiler invocation, its output is buffered until it exits so diagnostics of different jobs never interleave
+pub struct Job {
+    name: String,
+    command: Command,
+}
+impl Job {
+    pub fn new(name: String, command: Command) -> Self {
+        Self { name, command }
+    }
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+///runs jobs as separate processes with at most a fixed number of them alive at once
+pub struct JobServer {
+    jobs: usize,
+}
+impl JobServer {
+    pub fn new(jobs: usize) -> Self {
+        Self {
+            jobs: if jobs == 0 { 1 } else { jobs },
+        }
+    }
+    ///one job per cpu, used when neither -j nor the jobs key of build.toml is given
+    pub fn default_jobs() -> usize {
+        match thread::available_parallelism() {
+            Ok(count) => count.get(),
+            Err(_) => 1,
+        }
+    }
+    pub fn get_jobs(&self) -> usize {
+        self.jobs
+    }
+    ///runs every job, once one fails no new jobs are started and the names of all failed jobs are returned in the error
+    pub fn run(&self, jobs: Vec<Job>) -> std::io::Result<()> {
+        let workers = std::cmp::min(self.jobs, jobs.len());
+        let queue = Mutex::new(jobs.into_iter());
+        let failed: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let stop = AtomicBool::new(false);
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let mut job = match queue.lock().unwrap().next() {
+                        Some(job) => job,
+                        None => break,
+                    };
+                    let succeeded = match job.command.output() {
+                        Ok(op) => {
+                            let stdout = std::io::stdout();
+                            let stderr = std::io::stderr();
+                            let mut out = stdout.lock();
+                            let mut err = stderr.lock();
+                            let _ = out.write_all(&op.stdout);
+                            let _ = err.write_all(&op.stderr);
+                            op.status.success()
+                        }
+                        Err(e) => {
+                            eprintln!("error: {}: {}", job.name, e);
+                            false
+                        }
+                    };
+                    if !succeeded {
+                        stop.store(true, Ordering::SeqCst);
+                        failed.lock().unwrap().push(job.get_name());
+                    }
+                });
+            }
+        });
+        let failed = failed.into_inner().unwrap();
+        if !failed.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("{} failed", failed.join(", ")),
+            ));
+        }
+        Ok(())
+    }
+}
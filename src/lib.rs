@@ -1,8 +1,18 @@
 #[macro_use]
 extern crate lazy_static;
 pub mod arguments;
+pub mod cache;
+pub mod config;
 pub mod program;
 pub mod compiler;
+pub mod depfile;
+pub mod jobserver;
+pub mod local;
+pub mod lockfile;
+pub mod resolver;
+pub mod toolchain;
 pub mod project;
+pub mod registry;
 pub mod upstream;
+pub mod workspace;
 pub mod runtime;
\ No newline at end of file
@@ -0,0 +1,196 @@
+use crate::lockfile::Source;
+use crate::program::{project_relative, BuildMode, Program};
+use crate::project::{Dependency, Profile, Project};
+use crate::toolchain::Toolchain;
+use crate::upstream::{generate_pc, prepend_pkg_config_path};
+use std::fs::create_dir_all;
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+lazy_static! {
+    ///the git and path dependencies being built, a dependency that shows up again depends on itself
+    static ref BUILDING: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+///a git or path dependency once its source is on disk
+#[derive(Debug, Clone)]
+pub struct LocalDependency {
+    dir: String,
+    source: Source,
+    rev: Option<String>,
+}
+impl LocalDependency {
+    ///finds the source of depend, git dependencies are cloned or fetched into target/git/<name> of the project at path
+    ///with offline a git dependency is checked out from the clone made earlier without fetching
+    pub fn checkout(depend: &Dependency, path: &str, offline: bool) -> io::Result<Self> {
+        match (depend.get_git(), depend.get_path()) {
+            (Some(_), Some(_)) => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{} sets both git and path", depend.get_name()),
+            )),
+            (Some(url), None) => checkout_git(depend, &url, path, offline),
+            (None, Some(dir)) => Ok(Self {
+                dir: project_relative(path, &dir),
+                source: Source::Path,
+                rev: None,
+            }),
+            (None, None) => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{} is neither a git nor a path dependency", depend.get_name()),
+            )),
+        }
+    }
+    pub fn get_dir(&self) -> String {
+        self.dir.clone()
+    }
+    pub fn get_source(&self) -> Source {
+        self.source
+    }
+    ///the commit that was checked out, None for path dependencies
+    pub fn get_rev(&self) -> Option<String> {
+        self.rev.clone()
+    }
+    ///builds the dependency with the toolchain and profile of the dependent and installs it to deps/<name>
+    ///the .pc file written to deps/pkgconfig is put in front of PKG_CONFIG_PATH so the dependency is probed like any other
+    ///target is the target/ of the workspace the dependent belongs to, other dependencies build in their own target/
+    pub fn build(
+        &self,
+        depend: &Dependency,
+        deps: &str,
+        target: Option<&str>,
+        toolchain: &Toolchain,
+        profile_name: &str,
+        profile: &Profile,
+    ) -> io::Result<()> {
+        let dir = std::fs::canonicalize(&self.dir)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}: {}", depend.get_name(), self.dir, e)))?;
+        let dir = format!("{}", dir.display());
+        {
+            let mut building = BUILDING.lock().unwrap();
+            if let Some(start) = building.iter().position(|d| d == &dir) {
+                let mut cycle = building[start..].to_vec();
+                cycle.push(dir);
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("git and path dependencies depend on each other: {}", cycle.join(" -> ")),
+                ));
+            }
+            building.push(dir.clone());
+        }
+        let built = build_project(depend, &dir, deps, target, toolchain, profile_name, profile);
+        BUILDING.lock().unwrap().pop();
+        built
+    }
+}
+fn build_project(
+    depend: &Dependency,
+    dir: &str,
+    deps: &str,
+    target: Option<&str>,
+    toolchain: &Toolchain,
+    profile_name: &str,
+    profile: &Profile,
+) -> io::Result<()> {
+    let project = Project::from_file(dir)?;
+    if project.get_name() != depend.get_name() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{} points at {}, which is called {}", depend.get_name(), dir, project.get_name()),
+        ));
+    }
+    if project.get_type() != "lib" {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{} is not a library", depend.get_name()),
+        ));
+    }
+    let mut program = Program::new(&project, dir);
+    program.set_toolchain(toolchain.clone());
+    if let Some(target) = target {
+        program.set_target_dir(target.to_string());
+    }
+    program.inherit_profile(profile_name, profile)?;
+    program.build(dir, BuildMode::Normal)?;
+    //the prefix is absolute so the include path and rpath of the .pc file work from any directory
+    create_dir_all(deps)?;
+    let deps = format!("{}", std::fs::canonicalize(deps)?.display());
+    let prefix = format!("{}/{}", deps, project.get_name());
+    program.install(dir, &prefix)?;
+    create_dir_all(format!("{}/pkgconfig", deps))?;
+    generate_pc(
+        &project.get_package(),
+        &prefix,
+        &format!("{}/pkgconfig/{}.pc", deps, project.get_name()),
+    )?;
+    prepend_pkg_config_path(&format!("{}/pkgconfig", deps));
+    Ok(())
+}
+///clones url into target/git/<name>, or fetches it again if it was cloned before, then checks out rev, tag or branch
+///without any of them the default branch of the repository is used
+fn checkout_git(depend: &Dependency, url: &str, path: &str, offline: bool) -> io::Result<LocalDependency> {
+    let target = match (depend.get_rev(), depend.get_tag(), depend.get_branch()) {
+        (Some(rev), None, None) => rev,
+        (None, Some(tag), None) => format!("refs/tags/{}", tag),
+        (None, None, Some(branch)) => format!("origin/{}", branch),
+        (None, None, None) => "origin/HEAD".to_string(),
+        _ => {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{} sets more than one of rev, tag and branch", depend.get_name()),
+            ))
+        }
+    };
+    //local repositories are relative to the project like path dependencies, git itself would read them relative to the checkout
+    let url = if url.contains("://") || Path::new(url).is_absolute() {
+        url.to_string()
+    } else {
+        let repository = project_relative(path, url);
+        match std::fs::canonicalize(&repository) {
+            Ok(repository) => format!("{}", repository.display()),
+            Err(_) => repository,
+        }
+    };
+    let dir = format!("{}/target/git/{}", path, depend.get_name());
+    if Path::new(&format!("{}/.git", dir)).exists() {
+        //offline the commit, tag or branch has to be in the clone already
+        if !offline {
+            git(&dir, &["remote", "set-url", "origin", &url])?;
+            git(&dir, &["fetch", "--quiet", "--tags", "origin"])?;
+            git(&dir, &["remote", "set-head", "origin", "--auto"])?;
+        }
+    } else if offline {
+        return Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("--offline is set but {} has not been cloned yet", depend.get_name()),
+        ));
+    } else {
+        create_dir_all(format!("{}/target/git", path))?;
+        let mut clone = Command::new("git");
+        clone.args(&["clone", "--quiet", &url, &dir]);
+        run_git(clone, &format!("cloning {}", url))?;
+    }
+    git(&dir, &["checkout", "--quiet", "--detach", &target])?;
+    let rev = git(&dir, &["rev-parse", "HEAD"])?;
+    Ok(LocalDependency {
+        dir,
+        source: Source::Git,
+        rev: Some(rev),
+    })
+}
+///runs git in dir and returns what it printed
+fn git(dir: &str, args: &[&str]) -> io::Result<String> {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(dir).args(args);
+    run_git(command, &format!("git {}", args.join(" ")))
+}
+fn run_git(mut command: Command, description: &str) -> io::Result<String> {
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            ErrorKind::Other,
+            format!("{} failed: {}", description, String::from_utf8_lossy(&output.stderr).trim()),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,280 @@
+use crate::project::Dependency;
+use crate::upstream::{installed_checksum, sha256_file, PROGRAM_DATA};
+use serde_derive::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
+///where the files of a locked dependency come from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    System,
+    Registry,
+    Git,
+    Path,
+}
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Source::System => write!(f, "system"),
+            Source::Registry => write!(f, "registry"),
+            Source::Git => write!(f, "git"),
+            Source::Path => write!(f, "path"),
+        }
+    }
+}
+///the version chosen for one dependency of build.toml
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LockedPackage {
+    name: String,
+    version: String,
+    ///the requirement of build.toml the version was chosen for, the lock is out of date once it changes
+    requirement: String,
+    source: Source,
+    pc_file: Option<String>,
+    checksum: Option<String>,
+    ///the commit a git dependency was built from
+    rev: Option<String>,
+}
+impl LockedPackage {
+    pub fn new(
+        name: String,
+        version: String,
+        requirement: String,
+        source: Source,
+        pc_file: Option<String>,
+        checksum: Option<String>,
+    ) -> Self {
+        Self {
+            name,
+            version,
+            requirement,
+            source,
+            pc_file,
+            checksum,
+            rev: None,
+        }
+    }
+    ///records the library pkg-config found for depend
+    ///the checksum of a registry package is the sha256 of its archive, for system libraries it is the sha256 of the .pc file
+    pub fn from_library(depend: &Dependency, lib: &pkg_config::Library) -> Self {
+        let pc_file = match pkg_config::get_variable(&depend.get_name(), "pcfiledir") {
+            Ok(dir) if !dir.is_empty() => Some(format!("{}/{}.pc", dir, depend.get_name())),
+            _ => None,
+        };
+        //packages fetched from the registry are unpacked below ~/.cppbuild
+        let source = match &pc_file {
+            Some(file) if file.starts_with(PROGRAM_DATA.lock().unwrap().as_str()) => Source::Registry,
+            _ => Source::System,
+        };
+        //the .pc file of a registry package holds the path of ~/.cppbuild, which differs between machines
+        let checksum = match source {
+            Source::Registry => installed_checksum(&depend.get_name(), &lib.version),
+            _ => pc_file.as_ref().and_then(|file| sha256_file(file).ok()),
+        };
+        Self::new(
+            depend.get_name(),
+            lib.version.clone(),
+            depend.get_version(),
+            source,
+            pc_file,
+            checksum,
+        )
+    }
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn get_version(&self) -> String {
+        self.version.clone()
+    }
+    pub fn get_requirement(&self) -> String {
+        self.requirement.clone()
+    }
+    pub fn get_source(&self) -> Source {
+        self.source
+    }
+    pub fn get_pc_file(&self) -> Option<String> {
+        self.pc_file.clone()
+    }
+    pub fn get_checksum(&self) -> Option<String> {
+        self.checksum.clone()
+    }
+    ///git and path dependencies are built by the project instead of being found by pkg-config
+    pub fn set_source(&mut self, source: Source, rev: Option<String>) {
+        self.source = source;
+        self.rev = rev;
+    }
+    pub fn get_rev(&self) -> Option<String> {
+        self.rev.clone()
+    }
+}
+///cppbuild.lock, the exact version of every dependency so every machine builds against the same libraries
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+impl Lockfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    ///path of the lock of the project at path
+    pub fn path(path: &str) -> String {
+        format!("{}/cppbuild.lock", path)
+    }
+    pub fn exists(path: &str) -> bool {
+        Path::new(&Self::path(path)).exists()
+    }
+    pub fn from_file(path: &str) -> io::Result<Self> {
+        let mut content = String::new();
+        File::open(Self::path(path))?.read_to_string(&mut content)?;
+        match toml::from_str(&content) {
+            Ok(lock) => Ok(lock),
+            Err(e) => Err(io::Error::new(ErrorKind::InvalidData, e)),
+        }
+    }
+    ///writes cppbuild.lock to the root of the project, packages are sorted by name so the file only changes when a version does
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let content = match toml::to_string(&self.sorted()) {
+            Ok(content) => content,
+            Err(e) => return Err(io::Error::new(ErrorKind::Other, e)),
+        };
+        let mut file = File::create(Self::path(path))?;
+        file.write_all(b"# This file is generated by cppbuild, it is not meant to be edited by hand.\n")?;
+        file.write_all(content.as_bytes())?;
+        Ok(())
+    }
+    ///the lock with its packages sorted by name as they are in cppbuild.lock, resolving gives them in dependency order
+    pub fn sorted(&self) -> Self {
+        let mut lock = self.clone();
+        lock.package.sort_by(|a, b| a.name.cmp(&b.name));
+        lock
+    }
+    pub fn push(&mut self, package: LockedPackage) {
+        self.package.retain(|p| p.name != package.name);
+        self.package.push(package);
+    }
+    pub fn find(&self, name: &str) -> Option<&LockedPackage> {
+        self.package.iter().find(|p| p.name == name)
+    }
+    pub fn get_packages(&self) -> &[LockedPackage] {
+        self.package.as_slice()
+    }
+    ///the locked version of depend, as long as build.toml still asks for the same requirement
+    pub fn locked_version(&self, depend: &Dependency) -> Option<String> {
+        match self.find(&depend.get_name()) {
+            Some(locked) if locked.requirement == depend.get_version() => Some(locked.get_version()),
+            _ => None,
+        }
+    }
+    ///checks that resolved picked the same version of the same dependencies as the lock, used by --locked
+    ///registry packages must also come from the same archive, paths and the checksums of system .pc files are not compared
+    ///since they differ between machines with the same version installed
+    pub fn verify(&self, resolved: &Lockfile) -> io::Result<()> {
+        let mut problems = Vec::new();
+        for package in resolved.package.iter() {
+            match self.find(&package.name) {
+                None => problems.push(format!("{} is not locked", package.name)),
+                Some(locked) if locked.requirement != package.requirement => problems.push(format!(
+                    "{} was locked for {} but build.toml asks for {}",
+                    package.name, locked.requirement, package.requirement
+                )),
+                Some(locked) if locked.version != package.version => problems.push(format!(
+                    "{} is locked at {} but {} was found",
+                    package.name, locked.version, package.version
+                )),
+                Some(locked) if locked.rev.is_some() && locked.rev != package.rev => problems.push(format!(
+                    "{} is locked at commit {} but {} was checked out",
+                    package.name,
+                    locked.rev.clone().unwrap_or_default(),
+                    package.rev.clone().unwrap_or_default()
+                )),
+                Some(locked)
+                    if locked.source == Source::Registry
+                        && package.source == Source::Registry
+                        && locked.checksum.is_some()
+                        && package.checksum.is_some()
+                        && locked.checksum != package.checksum =>
+                {
+                    problems.push(format!(
+                        "{} {} was locked with the archive {} but {} is installed",
+                        package.name,
+                        package.version,
+                        locked.checksum.clone().unwrap_or_default(),
+                        package.checksum.clone().unwrap_or_default()
+                    ))
+                }
+                Some(_) => (),
+            }
+        }
+        for locked in self.package.iter() {
+            if resolved.find(&locked.name).is_none() {
+                problems.push(format!("{} is locked but not a dependency", locked.name));
+            }
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            ErrorKind::Other,
+            format!("cppbuild.lock is out of date: {}", problems.join(", ")),
+        ))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::{LockedPackage, Lockfile, Source};
+    fn package(name: &str, source: Source, checksum: &str) -> LockedPackage {
+        LockedPackage::new(
+            name.to_string(),
+            "1.2.11".to_string(),
+            "^1.2".to_string(),
+            source,
+            None,
+            Some(checksum.to_string()),
+        )
+    }
+    fn lock(source: Source, checksum: &str) -> Lockfile {
+        let mut lock = Lockfile::new();
+        lock.push(package("zlib", source, checksum));
+        lock
+    }
+    #[test]
+    fn order_of_packages_does_not_matter_once_sorted() {
+        let mut resolved = Lockfile::new();
+        resolved.push(package("zlib", Source::System, "abc"));
+        resolved.push(package("png", Source::System, "def"));
+        let mut written = Lockfile::new();
+        written.push(package("png", Source::System, "def"));
+        written.push(package("zlib", Source::System, "abc"));
+        assert_ne!(resolved, written);
+        assert_eq!(resolved.sorted(), written.sorted());
+    }
+    #[test]
+    fn same_archive_passes() {
+        assert!(lock(Source::Registry, "abc").verify(&lock(Source::Registry, "abc")).is_ok());
+    }
+    #[test]
+    fn republished_archive_fails() {
+        let error = lock(Source::Registry, "abc").verify(&lock(Source::Registry, "def")).unwrap_err();
+        assert!(error.to_string().contains("zlib 1.2.11 was locked with the archive abc but def is installed"));
+    }
+    #[test]
+    fn system_pc_files_may_differ() {
+        assert!(lock(Source::System, "abc").verify(&lock(Source::System, "def")).is_ok());
+    }
+    #[test]
+    fn version_change_fails() {
+        let mut resolved = Lockfile::new();
+        resolved.push(LockedPackage::new(
+            "zlib".to_string(),
+            "1.2.12".to_string(),
+            "^1.2".to_string(),
+            Source::System,
+            None,
+            None,
+        ));
+        assert!(lock(Source::System, "abc").verify(&resolved).is_err());
+    }
+}
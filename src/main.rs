@@ -3,18 +3,31 @@
 extern crate lazy_static;
 extern crate toml;
 pub mod arguments;
+pub mod cache;
+pub mod config;
 pub mod program;
 pub mod project;
+pub mod registry;
 pub mod upstream;
+pub mod workspace;
 pub mod compiler;
+pub mod depfile;
+pub mod jobserver;
+pub mod local;
+pub mod lockfile;
+pub mod resolver;
+pub mod toolchain;
 use compiler::*;
-use arguments::Arguments;
+use arguments::{Arg, Arguments};
+use cache::Cache;
 use program::*;
 use project::*;
 use std::fs::create_dir;
 use std::fs::File;
 use std::io::Write;
+use toolchain::{Toolchain, ToolchainConfig};
 use upstream::*;
+use workspace::{Member, Workspace};
 
 
 pub fn print_help(code: i32) -> ! {
@@ -24,10 +37,37 @@ pub fn print_help(code: i32) -> ! {
         \t --lib for library
         --help \t\t displays this message.
         --get-flags \t\t prints flags sent to g++.
-        fetch name version \t downloads the package and saves it to ~/.cppbuild
+        fetch name version \t downloads the package and the packages it depends on to ~/.cppbuild
+        \t without a name every registry dependency of build.toml is fetched, checksum = \"<sha256>\" on a [[dependency]] pins its archive
+        \t cppbuild packages are built with the toolchain and --profile of the current project and installed to ~/.cppbuild/installed
+        \t archives are kept in ~/.cppbuild/cache, --offline only uses the cache and fails if anything is missing from it
         publish \t\t generates a tar ball with the source code of the project
-        build \t\t builds project and outputs it in target/
+        \t <path> <type> packs a make, shell or raw package whose build.toml sets the same package_type
+        \t <lib> pkgconfig captures the headers, libraries and .pc file of a library installed on this machine
+        \t --registry <name> adds the tar ball to a directory registry listed in ~/.cppbuild/config.toml
+        build \t\t builds project and outputs it in target/<profile>/
+        \t --profile <name> builds with the [profile.<name>] table of build.toml
+        \t --release, --debug shorthands for --profile release and --profile debug
+        \t -j, --jobs <n> number of files compiled in parallel, defaults to the number of cpus
+        \t --compile-commands writes compile_commands.json for clangd and other editors
+        \t --compiler <cxx> c++ compiler to use, overrides CXX and the [toolchain] table of build.toml
+        \t --locked fails instead of updating cppbuild.lock when it does not match build.toml
+        \t --offline builds git dependencies from their last checkout instead of fetching them
+        \t -p, --package <member> builds one member of a workspace, every member is built in dependency order without it
         run \t\t runs the program, will only run if it is a binary
+        \t -p, --package <member> runs a binary member of a workspace
+        test \t\t builds and runs every function marked ///test, each in its own process, and exits with 101 if any failed
+        \t tests can #include \"cppbuild_test.h\" for ASSERT_TRUE, ASSERT_EQ, ASSERT_NEAR, ASSERT_THROWS and the other assertions
+        \t <filter> only runs the tests whose name contains filter, --exact only the test called filter, only one filter is allowed
+        \t --list lists the tests instead of running them
+        \t --include-ignored also runs the tests marked ///test ignore
+        \t --test-timeout <duration> fails tests still running after it, 60s by default, ///test timeout=5s sets it for one test
+        \t --format <junit|tap|json> reports the duration, output and failure message of every test for ci instead of the usual output
+        \t --output <file> writes the report of --format to file and keeps the usual output on the terminal
+        cache \t\t manages the archives kept in ~/.cppbuild/cache, or the cache directory set in config.toml
+        \t list shows every cached archive with its checksum
+        \t clean removes the whole cache
+        \t prune removes all but the newest version of each package, --keep <n> keeps n versions
         clean \t\t removes everything in target/"
     );
     println!("error code: {}", code);
@@ -46,7 +86,21 @@ fn main() {
         } else {
             BuildMode::Normal
         };
-        Program::new(&Project::from_file(".").unwrap(), ".")
+        let project = Project::from_file(".").unwrap();
+        if let Some(workspace) = project.get_workspace() {
+            if let Err(e) = build_workspace(workspace, args, mode) {
+                println!("error: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        no_workspace_member(args);
+        let mut program = Program::new(&project, ".");
+        configure(&mut program, args);
+        if args.has_arg("--compile-commands") {
+            program.set_compile_commands(true);
+        }
+        program
             .build(".", mode)
             .expect("unable to build the program due to an io error");
     });
@@ -61,13 +115,31 @@ fn main() {
         } else {
             BuildMode::Normal
         };
+        let project = Project::from_file(".").unwrap();
+        if let Some(workspace) = project.get_workspace() {
+            let member = match get_member(args) {
+                Some(name) => workspace.find(&name),
+                None => Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "build.toml is a workspace, choose the member to run with -p <member>",
+                )),
+            };
+            let ran = member.and_then(|member| member_program(workspace, member, args).run(&member.get_path(), mode));
+            if let Err(e) = ran {
+                println!("error: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        no_workspace_member(args);
         if mode.is_normal(){
-        Program::new(&Project::from_file(".").unwrap(), ".")
+        let mut program = Program::new(&project, ".");
+        configure(&mut program, args);
+        program
             .run(".", mode)
             .expect("unable to build the program due to an io error");
         }else if mode == BuildMode::Example{
             let examples = Example::new(".").unwrap();
-            let mut program = Program::new(&Project::from_file(".").unwrap(), ".");
             let flag = match args.get_flag("--example"){
                 Some(flag) => flag,
                 None => print_help(8),
@@ -78,7 +150,8 @@ fn main() {
                 print_help(9);
             }
 
-            program.append_source_file(examples.find(&values[0].get_name()).unwrap());
+            let mut program = Program::example(&project, ".", &examples.find(&values[0].get_name()).unwrap());
+            configure(&mut program, args);
             program.run(".", mode).unwrap();
         }
     });
@@ -116,52 +189,361 @@ fn main() {
         )
         .unwrap();
     });
-    args.invoke_callback("publish", &move |flags, _| {
-        match flags.get(0) {
-            Some(f) => {
-                let buildtype = match flags.get(1) {
-                    Some(_) => {
-                        PackageType::PkgConfig
-                        //PackageType::form_string(&kind.get_name());
-                    }
-                    None => PackageType::CppBuild,
-                };
-                generate_package(buildtype, f.get_name().as_str()).unwrap()
-            }
-            None => generate_package(PackageType::CppBuild, ".").unwrap(),
+    args.invoke_callback("publish", &move |flags, args| {
+        let path = match flags.get(0) {
+            Some(f) => f.get_name(),
+            None => ".".to_string(),
+        };
+        let buildtype = match flags.get(1) {
+            Some(kind) => match PackageType::from_name(&kind.get_name()) {
+                Ok(kind) => kind,
+                Err(e) => {
+                    println!("error: {}", e);
+                    print_help(15);
+                }
+            },
+            None => PackageType::CppBuild,
+        };
+        let published = match args.get_flag("--registry") {
+            Some(flag) => match flag.get_values().get(0) {
+                Some(registry) => publish_package(buildtype, &path, &registry.get_name()),
+                None => print_help(14),
+            },
+            None => generate_package(buildtype, &path).map(|_| ()),
+        };
+        if let Err(e) = published {
+            println!("error: {}", e);
+            std::process::exit(1);
         }
     });
-    args.invoke_callback("--get-flags", &move |_, _| {
-        println!(
-            "{}",
-            Program::new(&Project::from_file(".").unwrap(), ".").get_flags()
-        );
+    args.invoke_callback("--get-flags", &move |_, args| {
+        let mut program = Program::new(&Project::from_file(".").unwrap(), ".");
+        configure(&mut program, args);
+        println!("{}", program.get_flags());
     });
-    args.invoke_callback("fetch", &move |vals, _| {
-        let project_name = match vals.get(0) {
-            Some(arg) => arg.get_name(),
-            None => print_help(6),
+    args.invoke_callback("fetch", &move |vals, args| {
+        let toolchain = get_toolchain(args);
+        let dependencies = fetch_dependencies(vals);
+        let name = get_profile(args);
+        let fetched = resolve_profile(&name)
+            .and_then(|profile| download_packages(&dependencies, &toolchain, &name, &profile, args.has_arg("--offline")));
+        if let Err(e) = fetched {
+            println!("error: {}", e);
+            std::process::exit(1);
+        }
+    });
+    args.invoke_callback("cache", &move |flags, args| {
+        let cache = match Cache::open() {
+            Ok(cache) => cache,
+            Err(e) => {
+                println!("error: {}", e);
+                std::process::exit(1);
+            }
         };
-        let project_version = match vals.get(1) {
-            Some(arg) => arg.get_name(),
-            None => print_help(7),
+        let command = match flags.get(0) {
+            Some(command) => command.get_name(),
+            None => print_help(17),
+        };
+        let done = match command.as_str() {
+            "list" => cache.list().map(|archives| {
+                for archive in archives.iter() {
+                    println!(
+                        "{} {} {} {} bytes",
+                        archive.get_name(),
+                        archive.get_version(),
+                        archive.get_checksum(),
+                        archive.get_size()
+                    );
+                }
+            }),
+            "clean" => cache.clean().map(|_| println!("removed {}", cache.get_dir())),
+            "prune" => {
+                let keep = match args.get_flag("--keep") {
+                    Some(flag) => match flag.get_values().get(0).map(|keep| keep.get_name().parse::<usize>()) {
+                        Some(Ok(keep)) => keep,
+                        _ => print_help(18),
+                    },
+                    None => 1,
+                };
+                cache.prune(keep).map(|removed| {
+                    for archive in removed.iter() {
+                        println!("removed {} {} {}", archive.get_name(), archive.get_version(), archive.get_checksum());
+                    }
+                })
+            }
+            _ => print_help(17),
         };
-        download_packages(&[(project_name, project_version)]).unwrap();
+        if let Err(e) = done {
+            println!("error: {}", e);
+            std::process::exit(1);
+        }
     });
-    args.invoke_callback("test", &move |_, _| {
-        create_test();
+    args.invoke_callback("test", &move |flags, args| {
+        create_test(flags, args);
     });
     args.invoke_callback("clean", &move |_, _| {
         std::fs::remove_dir_all("./target").unwrap();
     });
     args.parse();
 }
-pub fn create_test() {
+///reads the number of parallel jobs from -j <n> or --jobs <n>
+pub fn get_jobs(args: &Arguments) -> Option<usize> {
+    let value = match args.get_flag("--jobs") {
+        Some(flag) => match flag.get_values().get(0) {
+            Some(value) => value.get_name(),
+            None => print_help(10),
+        },
+        None => args.get_arg_value("-j")?.get_name(),
+    };
+    match value.parse::<usize>() {
+        Ok(jobs) if jobs > 0 => Some(jobs),
+        _ => print_help(10),
+    }
+}
+///the packages fetch downloads, every registry dependency of build.toml when no name and version are given
+///a checksum pinned in build.toml is kept, also when the same name and requirement are given on the command line
+fn fetch_dependencies(vals: &[Arg]) -> Vec<Dependency> {
+    let manifest: Vec<Dependency> = if std::path::Path::new("build.toml").exists() {
+        match Project::from_file(".") {
+            Ok(project) => project
+                .get_dependencies()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|depend| !depend.is_local())
+                .collect(),
+            Err(e) => {
+                println!("error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        Vec::new()
+    };
+    let name = match vals.get(0) {
+        Some(arg) => arg.get_name(),
+        None if !manifest.is_empty() => return manifest,
+        None => print_help(6),
+    };
+    let version = match vals.get(1) {
+        Some(arg) => arg.get_name(),
+        None => print_help(7),
+    };
+    let mut depend = Dependency::new(name, version, None);
+    let pinned = manifest
+        .iter()
+        .find(|d| d.get_name() == depend.get_name() && d.get_version() == depend.get_version())
+        .and_then(|d| d.get_checksum());
+    depend.set_checksum(pinned);
+    vec![depend]
+}
+///the profile selected by --profile, --release or --debug
+pub fn get_profile(args: &Arguments) -> String {
+    match args.get_flag("--profile") {
+        Some(flag) => match flag.get_values().get(0) {
+            Some(name) => name.get_name(),
+            None => print_help(12),
+        },
+        None if args.has_arg("--release") => "release".to_string(),
+        None if args.has_arg("--debug") => "debug".to_string(),
+        None => "normal".to_string(),
+    }
+}
+///the profile fetched packages are built with, looked up in the project in the current directory if there is one
+pub fn resolve_profile(name: &str) -> std::io::Result<Profile> {
+    let profiles = match Project::from_file(".") {
+        Ok(project) => project.get_package().get_profiles(),
+        Err(_) => std::collections::HashMap::new(),
+    };
+    Profile::resolve(&profiles, name)
+}
+///the toolchain fetched packages are built with, the one of the project in the current directory if there is one
+pub fn get_toolchain(args: &Arguments) -> Toolchain {
+    let config = match Project::from_file(".") {
+        Ok(project) => project.get_package().get_toolchain(),
+        Err(_) => ToolchainConfig::default(),
+    };
+    let mut toolchain = Toolchain::new(&config);
+    if let Some(flag) = args.get_flag("--compiler") {
+        match flag.get_values().get(0) {
+            Some(compiler) => toolchain.set_cxx(compiler.get_name()),
+            None => print_help(11),
+        }
+    }
+    toolchain
+}
+///the workspace member selected by -p <member> or --package <member>
+pub fn get_member(args: &Arguments) -> Option<String> {
+    match args.get_flag("--package") {
+        Some(flag) => match flag.get_values().get(0) {
+            Some(member) => Some(member.get_name()),
+            None => print_help(16),
+        },
+        None => Some(args.get_arg_value("-p")?.get_name()),
+    }
+}
+///-p only means something for a workspace
+fn no_workspace_member(args: &Arguments) {
+    if get_member(args).is_some() {
+        println!("error: -p selects a member of a workspace, build.toml has no [workspace] table");
+        std::process::exit(1);
+    }
+}
+///a member of the workspace set up to build into the target/ of the workspace
+pub fn member_program(workspace: &Workspace, member: &Member, args: &Arguments) -> Program {
+    let mut program = Program::new(member.get_project(), &member.get_path());
+    program.set_target_dir(workspace.target_dir());
+    configure(&mut program, args);
+    if args.has_arg("--compile-commands") {
+        program.set_compile_commands(true);
+    }
+    program
+}
+///builds every member of the workspace after the members it depends on, or only the member chosen with -p
+pub fn build_workspace(workspace: &Workspace, args: &Arguments, mode: BuildMode) -> std::io::Result<()> {
+    let members = match get_member(args) {
+        Some(name) => vec![workspace.find(&name)?],
+        None => workspace.build_order()?,
+    };
+    for member in members {
+        println!("building {}", member.get_name());
+        member_program(workspace, member, args).build(&member.get_path(), mode)?;
+    }
+    Ok(())
+}
+///applies the options shared by every command that builds the project
+pub fn configure(program: &mut Program, args: &Arguments) {
+    if let Some(jobs) = get_jobs(args) {
+        program.set_jobs(jobs);
+    }
+    if let Some(flag) = args.get_flag("--compiler") {
+        match flag.get_values().get(0) {
+            Some(compiler) => program.set_compiler(compiler.get_name()),
+            None => print_help(11),
+        }
+    }
+    if args.has_arg("--locked") {
+        program.set_locked(true);
+    }
+    if args.has_arg("--offline") {
+        program.set_offline(true);
+    }
+    if let Err(e) = program.set_profile(&get_profile(args)) {
+        println!("error: {}", e);
+        print_help(13);
+    }
+}
+///builds the test harness and runs the tests matching the filter given after test
+///the filters given to test, test --list name gives name to --list and test --format junit name to --format
+///so the values after flags that take none and the ones after the value of a flag that takes one are filters too
+///-j <n> and -p <member> are not
+fn test_filters(flags: &[Arg], args: &Arguments) -> Vec<String> {
+    let switches = [
+        "--exact",
+        "--list",
+        "--include-ignored",
+        "--release",
+        "--debug",
+        "--locked",
+        "--offline",
+        "--compile-commands",
+    ];
+    let with_value = ["--format", "--output", "--test-timeout", "--profile", "--compiler", "--jobs", "--package"];
+    let mut values: Vec<Arg> = flags.to_vec();
+    for switch in switches.iter() {
+        if let Some(flag) = args.get_flag(switch) {
+            values.extend_from_slice(flag.get_values());
+        }
+    }
+    for flag in with_value.iter() {
+        if let Some(flag) = args.get_flag(flag) {
+            values.extend(flag.get_values().iter().skip(1).cloned());
+        }
+    }
+    let mut filters = Vec::new();
+    let mut values = values.iter().map(|value| value.get_name());
+    while let Some(value) = values.next() {
+        if value == "-j" || value == "-p" {
+            values.next();
+        } else {
+            filters.push(value);
+        }
+    }
+    filters
+}
+pub fn create_test(flags: &[Arg], args: &Arguments) {
     //generate_pc(&Project::from_file(".").unwrap().get_package(), ".").unwrap();
     let test = Test::from_file(".", "///test").unwrap();
     test.build_main().unwrap();
     let project = Project::from_file(".").unwrap();
-    let mut program = Program::new(&project, ".");
-    program.append_source_file(format!("{}/target/test_{}.cpp", test.get_dir(), test.get_name()));
-    program.run(".", BuildMode::Test).unwrap();
-}
\ No newline at end of file
+    let mut program = Program::test(&project, ".", &format!("{}/target/test_{}.cpp", test.get_dir(), test.get_name()));
+    configure(&mut program, args);
+    let filters = test_filters(flags, args);
+    if filters.len() > 1 {
+        print_help(22);
+    }
+    let mut harness_args = filters;
+    for flag in ["--exact", "--list", "--include-ignored"].iter() {
+        if args.has_arg(flag) {
+            harness_args.push(flag.to_string());
+        }
+    }
+    if let Some(flag) = args.get_flag("--test-timeout") {
+        match flag.get_values().get(0).and_then(|timeout| parse_duration(&timeout.get_name())) {
+            Some(timeout) => harness_args.extend(vec!["--test-timeout".to_string(), timeout.to_string()]),
+            None => print_help(19),
+        }
+    }
+    if let Some(flag) = args.get_flag("--format") {
+        match flag.get_values().get(0).map(|format| format.get_name()) {
+            Some(format) if ["human", "junit", "tap", "json"].contains(&format.as_str()) => {
+                harness_args.extend(vec!["--format".to_string(), format])
+            }
+            _ => print_help(20),
+        }
+    }
+    if let Some(flag) = args.get_flag("--output") {
+        match flag.get_values().get(0) {
+            Some(output) if args.has_arg("--format") => harness_args.extend(vec!["--output".to_string(), output.get_name()]),
+            _ => print_help(21),
+        }
+    }
+    match program.run_tests(".", &harness_args) {
+        Ok(true) => (),
+        Ok(false) => std::process::exit(101),
+        Err(e) => {
+            println!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::{test_filters, Arguments};
+    fn filters(command_line: &str) -> Vec<String> {
+        let mut args = Arguments::from_args(command_line.split_whitespace().map(|arg| arg.to_string()).collect());
+        args.parse();
+        let values = args.get_flag("test").unwrap().get_value_vec();
+        test_filters(&values, &args)
+    }
+    #[test]
+    fn jobs_and_members_are_not_filters() {
+        assert!(filters("test -j 4").is_empty());
+        assert_eq!(filters("test foo -j 4"), ["foo"]);
+        assert_eq!(filters("test -p member foo"), ["foo"]);
+        assert_eq!(filters("test --list -j 4 foo"), ["foo"]);
+    }
+    #[test]
+    fn filters_after_flags_are_kept() {
+        assert_eq!(filters("test --list foo"), ["foo"]);
+        assert_eq!(filters("test --include-ignored foo"), ["foo"]);
+        assert_eq!(filters("test --release foo"), ["foo"]);
+        assert_eq!(filters("test --compile-commands foo"), ["foo"]);
+        assert_eq!(filters("test --format junit name"), ["name"]);
+        assert_eq!(filters("test --test-timeout 5s --output report.xml name"), ["name"]);
+        assert!(filters("test --format junit --jobs 2").is_empty());
+    }
+    #[test]
+    fn every_filter_is_collected() {
+        assert_eq!(filters("test foo --list bar"), ["foo", "bar"]);
+    }
+}
@@ -1,10 +1,20 @@
-use crate::project::Project;
+use crate::depfile::DepFile;
+use crate::jobserver::{Job, JobServer};
+use crate::local::LocalDependency;
+use crate::lockfile::{LockedPackage, Lockfile};
+use crate::project::{Dependency, LibKind, Profile, Project};
+use crate::toolchain::Toolchain;
+use crate::upstream::{add_pkg_config_path, VersionReq};
+use serde_derive::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File};
 use std::io::Write;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use std::process::Command;
-use std::time::SystemTime;
 use walkdir::WalkDir;
+///the assertions of cppbuild_test.h, test builds get it on their include path
+const TEST_HEADER: &str = include_str!("harness/cppbuild_test.h");
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum BuildMode {
     Debug,
@@ -21,59 +31,416 @@ impl BuildMode {
 pub struct Program {
     name: String,
     sources: Box<RefCell<Vec<String>>>,
-    dependencies: Vec<String>,
-    include: Vec<String>,
+    libraries: Vec<pkg_config::Library>,
     program_type: String,
     standard: String,
+    jobs: usize,
+    compile_commands: bool,
+    toolchain: Toolchain,
+    version: String,
+    lib_kind: Vec<LibKind>,
+    flags: Profile,
+    profiles: HashMap<String, Profile>,
+    profile_name: String,
+    ///the selected profile before the flags at the top of build.toml are applied, dependencies inherit it
+    selected: Profile,
+    profile: Profile,
+    main_file: Option<String>,
+    lock: Lockfile,
+    previous_lock: Option<Lockfile>,
+    unresolved: Vec<String>,
+    locked: bool,
+    ///git and path dependencies, they are built at the start of build once the toolchain and profile are known
+    local: Vec<Dependency>,
+    ///the installed libraries of git and path dependencies, the program is linked again when one of them changes
+    local_libraries: Vec<String>,
+    ///the target/ of the workspace when the project is a member of one
+    target_dir: Option<String>,
+    offline: bool,
+}
+///one entry of compile_commands.json, holding the exact command used to compile a single source file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompileCommand {
+    pub directory: String,
+    pub file: String,
+    pub arguments: Vec<String>,
+    pub output: String,
 }
 impl Program {
-    ///responsible for building the program, outputs file into target. if it is a binary it is saved to target/main otherwise target/main.o
+    ///responsible for building the program, each source file is compiled to its own object in target/<profile>/obj/ and then linked
+    ///up to self.jobs compilers run in parallel
+    ///the compiler records the headers of every object in a .d file next to it, only objects whose source, headers or command line changed are recompiled
     ///path specifies the root of the project so the project can be build from other directories
     pub fn build(
         &mut self,
         path: &str,
         mode: BuildMode,
     ) -> std::result::Result<(), std::io::Error> {
-        let mut extra_args = Vec::new();
-        if mode == BuildMode::Release {
-            extra_args.push("-O3".to_string());
-        } else if mode == BuildMode::Debug {
-            extra_args.push("-g".to_string());
-        } //this will be used to link the .o file from this project
-        else if mode == BuildMode::Example || mode == BuildMode::Test{
-            //extra_args.push(format!("{}/target/lib{}.so", path, self.name));
-            self.program_type = "bin".to_string();
-            //shoud check if object file exists if not build it
-        }
-        if self.program_type == "lib" {
-            extra_args.push("-c".to_string());
-            extra_args.push("-o".to_string());
-            extra_args.push(format!("{}/target/lib{}.so", path, self.name));
-        } else {
-            extra_args.push("-o".to_string());
-            extra_args.push(format!("{}/target/{}", path, self.name));
-        }
-        if self.standard == "nostd"{
-            extra_args.push("-nostd".to_string());
-        }else{
-            extra_args.push(format!("-std={}", self.standard.clone()));
-        }
-        //extra_args.push("-static".to_string());
-        let op = Command::new("g++")
-            .args(&["-Iheaders/"])
-            .args(extra_args.iter())
-            .args(self.sources.get_mut())
-            .args(self.dependencies.iter())
-            .args(self.include.iter())
-            .output()?;
-        std::io::stdout().write_all(&op.stdout)?;
-        std::io::stderr().write_all(&op.stderr)?;
-        if mode == BuildMode::Example{
-            self.program_type = "lib".to_string();
+        for depend in std::mem::take(&mut self.local).iter() {
+            self.add_local_dependency(path, depend)?;
+        }
+        self.update_lock(path)?;
+        self.write_test_header(path)?;
+        if self.compile_commands {
+            self.write_compile_commands(path)?;
+        }
+        //the commands of the last successful build, an object built with different flags or another compiler is rebuilt
+        let record = format!("{}/commands.json", self.obj_dir(path));
+        let previous = read_compile_commands(&record);
+        let commands = self.get_compile_commands(path)?;
+        let mut objects = Vec::new();
+        let mut jobs = Vec::new();
+        for entry in commands.iter() {
+            let changed = !previous
+                .iter()
+                .any(|prev| prev.output == entry.output && prev.arguments == entry.arguments);
+            if changed || is_stale(&entry.file, &entry.output)? {
+                if let Some(parent) = Path::new(&entry.output).parent() {
+                    create_dir_all(parent)?;
+                }
+                let mut command = Command::new(&entry.arguments[0]);
+                command.args(&entry.arguments[1..]);
+                jobs.push(Job::new(format!("compiling {}", entry.file), command));
+            }
+            objects.push(entry.output.clone());
+        }
+        let compiled = !jobs.is_empty();
+        JobServer::new(self.jobs).run(jobs)?;
+        if compiled {
+            write_compile_commands(&record, &commands)?;
+        }
+        let linked: Vec<String> = objects.iter().chain(self.local_libraries.iter()).cloned().collect();
+        if self.is_binary(mode) {
+            let output = self.output_path(path, mode);
+            if let Some(parent) = Path::new(&output).parent() {
+                create_dir_all(parent)?;
+            }
+            if compiled || is_outdated(&output, &linked)? {
+                self.link(&output, &objects)?;
+            }
+            return Ok(());
+        }
+        for kind in self.lib_kind.iter() {
+            let output = self.library_path(path, *kind);
+            let inputs = match kind {
+                LibKind::Static => &objects,
+                LibKind::Shared => &linked,
+            };
+            if !compiled && !is_outdated(&output, inputs)? {
+                continue;
+            }
+            match kind {
+                LibKind::Static => self.archive(&output, &objects)?,
+                LibKind::Shared => self.link_shared(&output, &objects)?,
+            }
+        }
+        Ok(())
+    }
+    ///builds a git or path dependency and links it like a pkg-config dependency
+    fn add_local_dependency(&mut self, path: &str, depend: &Dependency) -> std::result::Result<(), std::io::Error> {
+        let local = LocalDependency::checkout(depend, path, self.offline)?;
+        let deps = format!("{}/deps", self.profile_dir(path));
+        let profile = self.get_profile();
+        local.build(depend, &deps, self.target_dir.as_deref(), &self.toolchain, &self.profile_name, &profile)?;
+        for entry in std::fs::read_dir(format!("{}/{}/lib", deps, depend.get_name()))? {
+            self.local_libraries.push(format!("{}", entry?.path().display()));
+        }
+        let requirement = VersionReq::parse(&depend.get_version())?;
+        let mut config = pkg_config::Config::new();
+        config.cargo_metadata(false);
+        requirement.configure(&mut config);
+        let lib = match config.probe(&depend.get_name()) {
+            Ok(lib) => lib,
+            Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("{}: {}", local.get_dir(), e))),
+        };
+        let mut locked = LockedPackage::from_library(depend, &lib);
+        locked.set_source(local.get_source(), local.get_rev());
+        self.lock.push(locked);
+        self.libraries.push(lib);
+        Ok(())
+    }
+    ///writes cppbuild.lock when the resolved versions changed, with --locked the build fails instead
+    ///the lock is only written once every dependency was found so a missing library does not drop it from the lock
+    fn update_lock(&self, path: &str) -> std::result::Result<(), std::io::Error> {
+        if self.locked {
+            if !self.unresolved.is_empty() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("unable to resolve {}", self.unresolved.join(", ")),
+                ));
+            }
+            return match &self.previous_lock {
+                Some(previous) => previous.verify(&self.lock),
+                None => Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "cppbuild.lock is missing, build without --locked to create it",
+                )),
+            };
+        }
+        //cppbuild.lock is sorted by name, so the order dependencies are declared in does not cause a rewrite
+        if self.unresolved.is_empty() && self.previous_lock.as_ref().map(Lockfile::sorted) != Some(self.lock.sorted()) {
+            self.lock.write(path)?;
+        }
+        Ok(())
+    }
+    ///links objects and the libraries of every dependency into an executable
+    fn link(&self, output: &str, objects: &[String]) -> std::result::Result<(), std::io::Error> {
+        let mut command = Command::new(self.toolchain.get_linker());
+        command
+            .args(self.toolchain.get_family().output_flags(output))
+            .args(objects.iter())
+            .args(self.link_flags())
+            .args(self.dependency_ldflags());
+        run_tool(command, &format!("linking {}", output))
+    }
+    ///include paths and defines of every pkg-config dependency
+    fn dependency_cflags(&self) -> Vec<String> {
+        let family = self.toolchain.get_family();
+        let mut flags = Vec::new();
+        for lib in self.libraries.iter() {
+            for dir in lib.include_paths.iter() {
+                flags.push(family.include_flag(&format!("{}", dir.display())));
+            }
+            for dir in lib.framework_paths.iter() {
+                flags.push(format!("-F{}", dir.display()));
+            }
+            //sorted so the command line stays the same between builds
+            let mut defines: Vec<_> = lib.defines.iter().collect();
+            defines.sort();
+            for (name, value) in defines {
+                match value {
+                    Some(value) => flags.push(family.define_flag(&format!("{}={}", name, value))),
+                    None => flags.push(family.define_flag(name)),
+                }
+            }
+        }
+        flags
+    }
+    ///link paths, libraries, frameworks and linker options of every pkg-config dependency
+    fn dependency_ldflags(&self) -> Vec<String> {
+        let family = self.toolchain.get_family();
+        let mut flags = Vec::new();
+        for lib in self.libraries.iter() {
+            for dir in lib.link_paths.iter() {
+                flags.push(family.link_path_flag(&format!("{}", dir.display())));
+            }
+            for dir in lib.framework_paths.iter() {
+                flags.push(format!("-F{}", dir.display()));
+            }
+            for name in lib.libs.iter() {
+                flags.push(family.lib_flag(name));
+            }
+            for framework in lib.frameworks.iter() {
+                flags.push("-framework".to_string());
+                flags.push(framework.clone());
+            }
+            for args in lib.ld_args.iter() {
+                flags.push(format!("-Wl,{}", args.join(",")));
+            }
+        }
+        flags
+    }
+    ///flags the profile adds to every link step
+    fn link_flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        if self.profile.lto == Some(true) {
+            flags.push(self.toolchain.get_family().lto_flag());
+        }
+        if let Some(ldflags) = &self.profile.ldflags {
+            flags.extend_from_slice(ldflags);
+        }
+        if let Some(libs) = &self.profile.libs {
+            let family = self.toolchain.get_family();
+            flags.extend(libs.iter().map(|lib| family.lib_flag(lib)));
         }
+        flags
+    }
+    ///bundles objects into a static library, the old archive is removed first so deleted sources do not linger in it
+    fn archive(&self, output: &str, objects: &[String]) -> std::result::Result<(), std::io::Error> {
+        if Path::new(output).exists() {
+            std::fs::remove_file(output)?;
+        }
+        let mut command = Command::new(self.toolchain.get_ar());
+        command.args(self.toolchain.get_family().archive_flags(output, objects));
+        run_tool(command, &format!("archiving {}", output))
+    }
+    ///links objects into lib<name>.so.<version> with the soname lib<name>.so.<major>, then links both shorter names to it
+    fn link_shared(&self, output: &str, objects: &[String]) -> std::result::Result<(), std::io::Error> {
+        let major = self.version.split('.').next().unwrap_or("0");
+        let soname = format!("lib{}.so.{}", self.name, major);
+        let mut command = Command::new(self.toolchain.get_linker());
+        command
+            .args(self.toolchain.get_family().shared_flags(&soname))
+            .args(self.toolchain.get_family().output_flags(output))
+            .args(objects.iter())
+            .args(self.link_flags())
+            .args(self.dependency_ldflags());
+        run_tool(command, &format!("linking {}", output))?;
+        let dir = match Path::new(output).parent() {
+            Some(dir) => dir.to_path_buf(),
+            None => PathBuf::from("."),
+        };
+        self.link_shared_names(&dir)
+    }
+    ///links lib<name>.so.<major> and lib<name>.so in dir to the shared library
+    fn link_shared_names(&self, dir: &Path) -> std::result::Result<(), std::io::Error> {
+        let major = self.version.split('.').next().unwrap_or("0");
+        let soname = format!("lib{}.so.{}", self.name, major);
+        let file_name = format!("lib{}.so.{}", self.name, self.version);
+        replace_symlink(&file_name, &dir.join(&soname))?;
+        replace_symlink(&soname, &dir.join(format!("lib{}.so", self.name)))?;
         Ok(())
     }
-    ///runs the program, checking first to see if any source code has been updated since last build
+    ///copies what build produced into prefix, executables to prefix/bin, libraries to prefix/lib and headers to prefix/include
+    pub fn install(&self, path: &str, prefix: &str) -> std::result::Result<(), std::io::Error> {
+        if self.program_type == "bin" {
+            create_dir_all(format!("{}/bin", prefix))?;
+            copy_if_changed(
+                Path::new(&self.output_path(path, BuildMode::Normal)),
+                Path::new(&format!("{}/bin/{}", prefix, self.name)),
+            )?;
+            return Ok(());
+        }
+        let lib_dir = PathBuf::from(format!("{}/lib", prefix));
+        create_dir_all(&lib_dir)?;
+        for kind in self.lib_kind.iter() {
+            let output = self.library_path(path, *kind);
+            if let Some(file_name) = Path::new(&output).file_name() {
+                copy_if_changed(Path::new(&output), &lib_dir.join(file_name))?;
+            }
+            if *kind == LibKind::Shared {
+                self.link_shared_names(&lib_dir)?;
+            }
+        }
+        //a library without public headers has nothing to install to include/
+        let headers = format!("{}/headers", path);
+        if !Path::new(&headers).is_dir() {
+            return Ok(());
+        }
+        copy_dir(&headers, &format!("{}/include", prefix))
+    }
+    ///the command line of every source file, these are the same commands build runs for files that need recompiling
+    pub fn get_compile_commands(
+        &self,
+        path: &str,
+    ) -> std::result::Result<Vec<CompileCommand>, std::io::Error> {
+        let directory = format!("{}", std::env::current_dir()?.display());
+        let family = self.toolchain.get_family();
+        let mut extra_args = vec![family.include_flag(&format!("{}/headers/", path))];
+        if let Some(level) = &self.profile.opt_level {
+            extra_args.push(family.opt_flag(&level.to_string()));
+        }
+        if self.profile.debug == Some(true) {
+            extra_args.push(family.debug_flag());
+        }
+        if self.profile.lto == Some(true) {
+            extra_args.push(family.lto_flag());
+        }
+        if let Some(defines) = &self.profile.defines {
+            extra_args.extend(defines.iter().map(|define| family.define_flag(define)));
+        }
+        if let Some(cflags) = &self.profile.cflags {
+            extra_args.extend_from_slice(cflags);
+        }
+        //objects of shared libraries must be position independent, examples and tests reuse them so they get it too
+        if self.program_type == "lib" && self.lib_kind.contains(&LibKind::Shared) {
+            extra_args.extend(family.pic_flag());
+        }
+        if let Some(dirs) = &self.profile.include_dirs {
+            extra_args.extend(dirs.iter().map(|dir| family.include_flag(&project_relative(path, dir))));
+        }
+        //sources in tests/ are compiled in every mode, so cppbuild_test.h is always on the include path
+        extra_args.push(family.include_flag(&self.test_include_dir(path)));
+        extra_args.append(&mut self.dependency_cflags());
+        let obj_dir = self.obj_dir(path);
+        let mut commands = Vec::new();
+        for source in self.sources.borrow().iter() {
+            let object = object_path(path, &obj_dir, source);
+            //c sources go to the c compiler and do not get the c++ standard flag
+            let mut arguments = if is_c_source(source) {
+                vec![self.toolchain.get_cc()]
+            } else {
+                let mut arguments = vec![self.toolchain.get_cxx()];
+                arguments.extend(family.std_flag(&self.standard));
+                arguments
+            };
+            arguments.extend_from_slice(&extra_args);
+            arguments.append(&mut family.depfile_flags(&depfile_path(&object)));
+            arguments.append(&mut family.compile_flags(source, &object));
+            commands.push(CompileCommand {
+                directory: directory.clone(),
+                file: source.clone(),
+                arguments,
+                output: object,
+            });
+        }
+        Ok(commands)
+    }
+    fn test_include_dir(&self, path: &str) -> String {
+        format!("{}/include", self.profile_dir(path))
+    }
+    ///writes cppbuild_test.h, it is left alone when it is already up to date so the tests including it are not rebuilt
+    fn write_test_header(&self, path: &str) -> std::result::Result<(), std::io::Error> {
+        let dir = self.test_include_dir(path);
+        let header = format!("{}/cppbuild_test.h", dir);
+        if std::fs::read_to_string(&header).ok().as_deref() == Some(TEST_HEADER) {
+            return Ok(());
+        }
+        create_dir_all(&dir)?;
+        File::create(&header)?.write_all(TEST_HEADER.as_bytes())
+    }
+    ///writes compile_commands.json to the root of the project so clangd and other tools see the same flags as the compiler
+    pub fn write_compile_commands(&self, path: &str) -> std::result::Result<(), std::io::Error> {
+        write_compile_commands(
+            &format!("{}/compile_commands.json", path),
+            &self.get_compile_commands(path)?,
+        )
+    }
+    ///examples and tests are always linked into an executable, even for lib projects
+    fn is_binary(&self, mode: BuildMode) -> bool {
+        self.program_type == "bin" || mode == BuildMode::Example || mode == BuildMode::Test
+    }
+    ///every profile builds into its own directory, target/<profile>/
+    pub fn profile_dir(&self, path: &str) -> String {
+        match &self.target_dir {
+            Some(target) => format!("{}/{}", target, self.profile_name),
+            None => format!("{}/target/{}", path, self.profile_name),
+        }
+    }
+    ///objects go to target/<profile>/obj/, members of a workspace share target/ so each gets obj/<name>/
+    fn obj_dir(&self, path: &str) -> String {
+        match &self.target_dir {
+            Some(_) => format!("{}/obj/{}", self.profile_dir(path), self.name),
+            None => format!("{}/obj", self.profile_dir(path)),
+        }
+    }
+    ///path of the executable produced by the link step, binaries are saved to target/<profile>/<name>
+    ///examples are saved to target/<profile>/examples/<example> and tests to target/<profile>/tests/<name>
+    pub fn output_path(&self, path: &str, mode: BuildMode) -> String {
+        let stem = self
+            .main_file
+            .as_ref()
+            .and_then(|file| Path::new(file).file_stem())
+            .map(|stem| stem.to_string_lossy().to_string());
+        match (mode, stem) {
+            (BuildMode::Example, Some(example)) => format!("{}/examples/{}", self.profile_dir(path), example),
+            (BuildMode::Test, _) => format!("{}/tests/{}", self.profile_dir(path), self.name),
+            _ => format!("{}/{}", self.profile_dir(path), self.name),
+        }
+    }
+    ///path of the library of the given kind, target/<profile>/lib<name>.a or target/<profile>/lib<name>.so.<version>
+    pub fn library_path(&self, path: &str, kind: LibKind) -> String {
+        match kind {
+            LibKind::Static => format!("{}/lib{}.a", self.profile_dir(path), self.name),
+            LibKind::Shared => format!(
+                "{}/lib{}.so.{}",
+                self.profile_dir(path),
+                self.name,
+                self.version
+            ),
+        }
+    }
+    ///runs the program, building first so any source code updated since the last build is recompiled
     pub fn run(&mut self, path: &str, mode: BuildMode) -> std::result::Result<(), std::io::Error> {
         if self.program_type != "bin"
             && (mode == BuildMode::Normal || mode == BuildMode::Release || mode == BuildMode::Debug)
@@ -95,20 +462,8 @@ impl Program {
                 "binary projects are not allowed to have tests",
             ));
         }
-        let pa = if self.program_type == "bin" {
-            format!("{}/target/{}", path, self.name)
-        } else if self.program_type == "lib" && !mode.is_normal() {
-            format!("{}/target/{}", path, self.name)
-        }else{
-            format!("{}/target/{}.o", path, self.name)
-        };
-        let p = Path::new(pa.as_str());
-        if !p.exists() {
-            self.build(path, mode)?;
-        }
-        if last_modified(path, std::fs::metadata(p)?.modified()?)? || mode == BuildMode::Release {
-            self.build(path, mode)?;
-        }
+        self.build(path, mode)?;
+        let pa = self.output_path(path, mode);
         if mode != BuildMode::Debug {
             let mut command = Command::new(pa.as_str());
             if let Ok(mut child) = command.spawn() {
@@ -129,100 +484,366 @@ impl Program {
         }
         Ok(())
     }
-    fn create(project: &Project, path: &str, mode: BuildMode, file: Option<&str>) -> Self {
+    ///builds the test harness and runs it with args, returns whether every test passed
+    pub fn run_tests(&mut self, path: &str, args: &[String]) -> std::result::Result<bool, std::io::Error> {
+        if self.program_type == "bin" {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "binary projects are not allowed to have tests",
+            ));
+        }
+        self.build(path, BuildMode::Test)?;
+        let status = Command::new(self.output_path(path, BuildMode::Test)).args(args).status()?;
+        Ok(status.success())
+    }
+    fn create(project: &Project, path: &str, file: Option<&str>) -> Self {
+        //packages fetched from a registry are found through the .pc files written when they were installed
+        add_pkg_config_path();
         let mut sources; // = Box::new(RefCell::new(Vec::new()));
-        let mut dependencies = Vec::new();
-        let mut include = Vec::new();
+        let mut libraries = Vec::new();
+        let previous_lock = if Lockfile::exists(path) {
+            match Lockfile::from_file(path) {
+                Ok(lock) => Some(lock),
+                Err(e) => {
+                    eprintln!("error: cppbuild.lock: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let mut lock = Lockfile::new();
+        let mut unresolved = Vec::new();
+        let mut local = Vec::new();
         match &project.get_dependencies() {
             Some(dep) => {
                 for depend in dep.iter() {
+                    if depend.is_local() {
+                        local.push(depend.clone());
+                        continue;
+                    }
                     let mut config = pkg_config::Config::new();
-                    if depend.get_version() != "*" {
-                        config.exactly_version(&depend.get_version());
+                    let requirement = match VersionReq::parse(&depend.get_version()) {
+                        Ok(requirement) => requirement,
+                        Err(e) => {
+                            eprintln!("error: {}: {}", depend.get_name(), e);
+                            unresolved.push(depend.get_name());
+                            continue;
+                        }
+                    };
+                    if let Some(statik) = depend.is_static() {
+                        config.statik(statik);
                     }
-                    
-                    match config.probe(depend.get_name().as_str()) {
-                        Ok(lib) => {
-                            for l in lib.libs {
-                                let li = format!("{}{}", "-l", l);
-                                dependencies.push(li);
-                            }
-                            for i in lib.include_paths {
-                                let inc = format!(
-                                    "{}{}",
-                                    "-I",
-                                    i.into_os_string().into_string().unwrap()
+                    //the locked version is preferred, the requirement is only resolved again if it is no longer installed
+                    let locked = previous_lock.as_ref().and_then(|lock| lock.locked_version(depend));
+                    let found = match locked {
+                        Some(version) => match config.exactly_version(&version).probe(depend.get_name().as_str()) {
+                            Ok(lib) => Ok(lib),
+                            Err(_) => {
+                                eprintln!(
+                                    "warning: {} {} from cppbuild.lock is not installed, resolving {}",
+                                    depend.get_name(),
+                                    version,
+                                    requirement
                                 );
-                                include.push(inc);
+                                requirement.configure(&mut config);
+                                config.probe(depend.get_name().as_str())
                             }
-                            // add all includes to one vector of args
+                        },
+                        None => {
+                            requirement.configure(&mut config);
+                            config.probe(depend.get_name().as_str())
+                        }
+                    };
+                    match found {
+                        Ok(lib) => {
+                            lock.push(LockedPackage::from_library(depend, &lib));
+                            libraries.push(lib);
+                        }
+                        Err(e) => {
+                            eprintln!("error: {}", e);
+                            unresolved.push(depend.get_name());
                         }
-                        Err(e) => println!("error: {}", e),
                     }
                 }
             }
             None => (),
         }
-        let walk: RefCell<walkdir::IntoIter> =
-            RefCell::new(WalkDir::new(format!("{}/src", path).as_str()).into_iter());
-        let build: RefCell<Vec<String>> = RefCell::new(Vec::new());
-        sources = add_file(walk, build);
-        sources.get_mut().append(&mut WalkDir::new(format!("{}/tests",path)).into_iter().filter(|e|{
-            e.as_ref().unwrap().path().is_file()
-        }).map(|e| {
-            format!("{}", e.unwrap().path().display())
-        }).collect());
+        sources = Box::new(RefCell::new(source_files(&format!("{}/src", path))));
+        sources.get_mut().append(&mut source_files(&format!("{}/tests", path)));
+        if let Some(file) = file {
+            sources.get_mut().push(file.to_string());
+        }
         Self {
             name: project.get_package().get_name(),
             sources,
-            dependencies,
-            include,
+            libraries,
             program_type: project.get_type(),
             standard: project.get_standard(),
+            jobs: match project.get_package().get_build().jobs {
+                Some(jobs) => jobs,
+                None => JobServer::default_jobs(),
+            },
+            compile_commands: project.get_package().get_build().compile_commands.unwrap_or(false),
+            toolchain: Toolchain::new(&project.get_package().get_toolchain()),
+            version: project.get_version(),
+            lib_kind: project.get_package().get_lib_kind(),
+            flags: project.get_package().get_flags(),
+            profiles: project.get_package().get_profiles(),
+            profile_name: "normal".to_string(),
+            selected: Profile::default(),
+            profile: project.get_package().get_flags(),
+            main_file: file.map(|f| f.to_string()),
+            lock,
+            previous_lock,
+            unresolved,
+            locked: false,
+            local,
+            local_libraries: Vec::new(),
+            target_dir: None,
+            offline: false,
         }
     }
+    ///overrides the number of parallel compile jobs, used by -j
+    pub fn set_jobs(&mut self, jobs: usize){
+        self.jobs = jobs;
+    }
+    ///selects the [profile.<name>] the program is built with, used by --profile, --release and --debug
+    ///the profile is applied on top of the flags at the top of build.toml
+    pub fn set_profile(&mut self, name: &str) -> std::result::Result<(), std::io::Error> {
+        self.selected = Profile::resolve(&self.profiles, name)?;
+        self.profile = self.selected.merge(&self.flags);
+        self.profile_name = name.to_string();
+        Ok(())
+    }
+    ///builds with the profile name of the project that needs this one, its own [profile.<name>] is used when it has one
+    ///otherwise profile, as resolved by the dependent, is applied on top of the flags at the top of build.toml
+    pub fn inherit_profile(&mut self, name: &str, profile: &Profile) -> std::result::Result<(), std::io::Error> {
+        if Profile::builtin(name).is_some() || self.profiles.contains_key(name) {
+            return self.set_profile(name);
+        }
+        self.selected = profile.clone();
+        self.profile = profile.merge(&self.flags);
+        self.profile_name = name.to_string();
+        Ok(())
+    }
+    ///the selected profile without the flags at the top of build.toml, dependencies inherit it
+    pub fn get_profile(&self) -> Profile {
+        self.selected.clone()
+    }
+    ///replaces the whole toolchain, fetched packages are built with the toolchain of the project that needs them
+    pub fn set_toolchain(&mut self, toolchain: Toolchain){
+        self.toolchain = toolchain;
+    }
+    ///overrides the c++ compiler, used by --compiler
+    pub fn set_compiler(&mut self, cxx: String){
+        self.toolchain.set_cxx(cxx);
+    }
+    ///when enabled every build also writes compile_commands.json, used by --compile-commands
+    pub fn set_compile_commands(&mut self, enabled: bool){
+        self.compile_commands = enabled;
+    }
+    ///when enabled git dependencies are not fetched, used by --offline
+    pub fn set_offline(&mut self, offline: bool){
+        self.offline = offline;
+    }
+    ///builds into target instead of the target/ of the project, used for the members of a workspace
+    pub fn set_target_dir(&mut self, target: String){
+        self.target_dir = Some(target);
+    }
+    ///when enabled the build fails unless cppbuild.lock matches the dependencies of build.toml, used by --locked
+    pub fn set_locked(&mut self, locked: bool){
+        self.locked = locked;
+    }
     pub fn append_source_file(&mut self, file: String){
         self.sources.get_mut().push(file);
     }
     pub fn example(project: &Project, path: &str, file: &str) -> Self {
-        Self::create(project, path, BuildMode::Example, Some(file))
+        Self::create(project, path, Some(file))
     }
     pub fn test(project: &Project, path: &str, file: &str) -> Self {
-        Self::create(project, path, BuildMode::Test, Some(file))
+        Self::create(project, path, Some(file))
     }
     ///creates the Program instance based on a project instance
     pub fn new(project: &Project, path: &str) -> Program {
-        Self::create(project, path, BuildMode::Normal, None)
+        Self::create(project, path, None)
     }
-    ///lists flags passed to g++ except for special flags
+    ///lists flags passed to the compiler except for special flags
     pub fn get_flags(&mut self) -> String {
+        let mut include: Vec<String> = self
+            .libraries
+            .iter()
+            .flat_map(|lib| lib.include_paths.iter().map(|dir| format!("{}", dir.display())))
+            .collect();
+        include.extend(self.profile.include_dirs.clone().unwrap_or_default());
+        let mut libraries: Vec<String> = self.libraries.iter().flat_map(|lib| lib.libs.clone()).collect();
+        libraries.extend(self.profile.libs.clone().unwrap_or_default());
+        let link_paths: Vec<String> = self
+            .libraries
+            .iter()
+            .flat_map(|lib| lib.link_paths.iter().map(|dir| format!("{}", dir.display())))
+            .collect();
         format!(
-            "include paths: {:?}\n libraries: {:?}\n source files: {:?}",
-            self.include,
-            self.dependencies,
+            "compiler: {}\n profile: {}\n include paths: {:?}\n link paths: {:?}\n defines: {:?}\n cflags: {:?}\n ldflags: {:?}\n libraries: {:?}\n dependency flags: {:?} {:?}\n source files: {:?}",
+            self.toolchain.get_cxx(),
+            self.profile_name,
+            include,
+            link_paths,
+            self.profile.defines.clone().unwrap_or_default(),
+            self.profile.cflags.clone().unwrap_or_default(),
+            self.profile.ldflags.clone().unwrap_or_default(),
+            libraries,
+            self.dependency_cflags(),
+            self.dependency_ldflags(),
             self.sources.get_mut()
         )
     }
 }
-///adds files to the Program struct so they can be bassed to g++
-pub fn add_file(
-    mut walk: RefCell<walkdir::IntoIter>,
-    mut build: RefCell<Vec<String>>,
-) -> Box<RefCell<Vec<String>>> {
-    let temppath = walk.get_mut().next().unwrap().unwrap();
-    let path = temppath.path();
-    if path.is_file() {
-        build.get_mut().push(path.to_str().unwrap().to_string());
-        return Box::new(build);
-    }
-    add_file(walk, build)
-}
-///checks if any file in a given path has been modified more recently then the given SystemTime passed in
-pub fn last_modified(path: &str, time: SystemTime) -> std::result::Result<bool, std::io::Error> {
-    for file in WalkDir::new(path).into_iter() {
-        let file = file?;
-        let metadata = std::fs::metadata(file.path()).unwrap();
-        if metadata.modified().unwrap() > time {
+///collects every c++ source file below dir so they can be passed to the compiler
+pub fn source_files(dir: &str) -> Vec<String> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file() && is_source(e.path()))
+        .map(|e| format!("{}", e.path().display()))
+        .collect()
+}
+///checks the extension of a file to see if it is compiled rather than included
+pub fn is_source(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ["cpp", "cc", "cxx", "c++", "c"].contains(&ext),
+        None => false,
+    }
+}
+///runs a compiler, archiver or linker, forwarding its output and turning a failed exit into an error
+pub fn run_tool(mut command: Command, description: &str) -> std::result::Result<(), std::io::Error> {
+    let op = command.output()?;
+    std::io::stdout().write_all(&op.stdout)?;
+    std::io::stderr().write_all(&op.stderr)?;
+    if !op.status.success() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("{} failed", description),
+        ));
+    }
+    Ok(())
+}
+///copies every file below from to the same place below to, files that did not change are left alone
+pub fn copy_dir(from: &str, to: &str) -> std::result::Result<(), std::io::Error> {
+    for entry in WalkDir::new(from).into_iter() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
+        };
+        let relative = entry.path().strip_prefix(from).unwrap_or_else(|_| entry.path());
+        let target = Path::new(to).join(relative);
+        if entry.file_type().is_dir() {
+            create_dir_all(&target)?;
+        } else {
+            copy_if_changed(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+///copies from over to unless to already holds the same bytes, an unchanged file keeps its modification time
+///so installing a dependency again does not make everything that includes its headers recompile
+fn copy_if_changed(from: &Path, to: &Path) -> std::result::Result<(), std::io::Error> {
+    if let (Ok(old), Ok(new)) = (std::fs::read(to), std::fs::read(from)) {
+        if old == new {
+            return Ok(());
+        }
+    }
+    std::fs::copy(from, to)?;
+    Ok(())
+}
+///points link at target, replacing whatever link was there before
+fn replace_symlink(target: &str, link: &Path) -> std::result::Result<(), std::io::Error> {
+    if link.symlink_metadata().is_ok() {
+        std::fs::remove_file(link)?;
+    }
+    symlink(target, link)
+}
+#[cfg(unix)]
+fn symlink(target: &str, link: &Path) -> std::result::Result<(), std::io::Error> {
+    std::os::unix::fs::symlink(target, link)
+}
+///symbolic links need extra privileges on windows, the file is copied instead, target is relative to the directory of link
+#[cfg(not(unix))]
+fn symlink(target: &str, link: &Path) -> std::result::Result<(), std::io::Error> {
+    let dir = link.parent().unwrap_or_else(|| Path::new("."));
+    std::fs::copy(dir.join(target), link).map(|_| ())
+}
+fn write_compile_commands(file: &str, commands: &[CompileCommand]) -> std::result::Result<(), std::io::Error> {
+    let json = match serde_json::to_string_pretty(commands) {
+        Ok(json) => json,
+        Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
+    };
+    let mut file = File::create(file)?;
+    file.write_all(json.as_bytes())?;
+    file.write_all(b"\n")?;
+    Ok(())
+}
+///reads commands written by write_compile_commands, a missing or unreadable file yields no commands
+fn read_compile_commands(file: &str) -> Vec<CompileCommand> {
+    match std::fs::read_to_string(file) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+///directories in build.toml are relative to the root of the project unless they are absolute
+pub fn project_relative(path: &str, dir: &str) -> String {
+    if Path::new(dir).is_absolute() {
+        dir.to_string()
+    } else {
+        format!("{}/{}", path, dir)
+    }
+}
+///c files are compiled with the c compiler of the toolchain
+fn is_c_source(source: &str) -> bool {
+    Path::new(source).extension().and_then(|ext| ext.to_str()) == Some("c")
+}
+///maps a source file to its object file inside obj_dir, mirroring the layout of the project
+fn object_path(path: &str, obj_dir: &str, source: &str) -> String {
+    let relative: PathBuf = Path::new(source)
+        .strip_prefix(path)
+        .unwrap_or_else(|_| Path::new(source))
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect();
+    format!("{}/{}.o", obj_dir, relative.display())
+}
+///the dependency file the compiler writes next to an object
+fn depfile_path(object: &str) -> String {
+    format!("{}.d", object.trim_end_matches(".o"))
+}
+///checks if an object file is missing or older than its source or any header it included
+fn is_stale(source: &str, object: &str) -> std::result::Result<bool, std::io::Error> {
+    let object_path = Path::new(object);
+    if !object_path.exists() {
+        return Ok(true);
+    }
+    let built = std::fs::metadata(object_path)?.modified()?;
+    if std::fs::metadata(source)?.modified()? > built {
+        return Ok(true);
+    }
+    match DepFile::from_file(&depfile_path(object)) {
+        Ok(deps) => Ok(deps.is_newer_than(built)),
+        //without a dependency file there is no way to know which headers were used
+        Err(_) => Ok(true),
+    }
+}
+///checks if the output of the link step is missing or older than any of its objects
+fn is_outdated(output: &str, objects: &[String]) -> std::result::Result<bool, std::io::Error> {
+    let output = Path::new(output);
+    if !output.exists() {
+        return Ok(true);
+    }
+    let linked = std::fs::metadata(output)?.modified()?;
+    for object in objects.iter() {
+        if std::fs::metadata(object)?.modified()? > linked {
             return Ok(true);
         }
     }
@@ -1,5 +1,9 @@
+use crate::toolchain::ToolchainConfig;
+use crate::upstream::PackageType;
+use crate::workspace::{Workspace, WorkspaceSettings};
 use clang::*;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::{create_dir, File};
 use std::io::Read;
 use std::path::Path;
@@ -13,12 +17,153 @@ pub struct Package {
     version: String,
     standard: String,
     project_type: Option<String>,
+    ///how the package is built once fetched, packages wrapping other build systems set make, shell or raw
+    package_type: Option<PackageType>,
     repository: Option<String>,
     owners: Vec<Owner>,
     pub dependency: Option<Vec<Dependency>>,
     dev_dependency: Option<Vec<Dependency>>,
     description: Option<String>,
     license: Option<String>,
+    defines: Option<Vec<String>>,
+    cflags: Option<Vec<String>>,
+    ldflags: Option<Vec<String>>,
+    libs: Option<Vec<String>>,
+    include_dirs: Option<Vec<String>>,
+    lib_kind: Option<Vec<LibKind>>,
+    profile: Option<HashMap<String, Profile>>,
+    build: Option<BuildSettings>,
+    toolchain: Option<ToolchainConfig>,
+    install: Option<InstallSettings>,
+}
+///the kinds of library a lib project produces, set with lib_kind = ["static", "shared"]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LibKind {
+    Static,
+    Shared,
+}
+///optimization level of a profile, written either as a number or as "s" and "z" for size
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum OptLevel {
+    Level(u32),
+    Name(String),
+}
+impl std::fmt::Display for OptLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OptLevel::Level(level) => write!(f, "{}", level),
+            OptLevel::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+///a [profile.<name>] table, keys that are left out are taken from the profile named by inherits
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub inherits: Option<String>,
+    pub opt_level: Option<OptLevel>,
+    pub debug: Option<bool>,
+    pub lto: Option<bool>,
+    pub defines: Option<Vec<String>>,
+    pub cflags: Option<Vec<String>>,
+    pub ldflags: Option<Vec<String>>,
+    pub libs: Option<Vec<String>>,
+    pub include_dirs: Option<Vec<String>>,
+}
+impl Profile {
+    ///the profiles every project has, normal is used when nothing is selected, debug for --debug and release for --release
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "normal" => Some(Self::default()),
+            "debug" => Some(Self {
+                debug: Some(true),
+                ..Self::default()
+            }),
+            "release" => Some(Self {
+                opt_level: Some(OptLevel::Level(3)),
+                ..Self::default()
+            }),
+            _ => None,
+        }
+    }
+    ///applies self on top of base, defines and flags are appended to the ones of base instead of replacing them
+    pub fn merge(&self, base: &Profile) -> Profile {
+        Profile {
+            inherits: None,
+            opt_level: self.opt_level.clone().or_else(|| base.opt_level.clone()),
+            debug: self.debug.or(base.debug),
+            lto: self.lto.or(base.lto),
+            defines: concat(&base.defines, &self.defines),
+            cflags: concat(&base.cflags, &self.cflags),
+            ldflags: concat(&base.ldflags, &self.ldflags),
+            libs: concat(&base.libs, &self.libs),
+            include_dirs: concat(&base.include_dirs, &self.include_dirs),
+        }
+    }
+    ///looks up a profile by name following inherits, a table named after a builtin profile is applied on top of it
+    ///custom profiles that do not say what they inherit from build on normal
+    pub fn resolve(profiles: &HashMap<String, Profile>, name: &str) -> std::io::Result<Profile> {
+        Self::resolve_chain(profiles, name, &mut Vec::new())
+    }
+    fn resolve_chain(
+        profiles: &HashMap<String, Profile>,
+        name: &str,
+        chain: &mut Vec<String>,
+    ) -> std::io::Result<Profile> {
+        chain.push(name.to_string());
+        if chain[..chain.len() - 1].contains(&name.to_string()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("profiles inherit from each other: {}", chain.join(" -> ")),
+            ));
+        }
+        let table = profiles.get(name);
+        let base = match (table.and_then(|t| t.inherits.clone()), Self::builtin(name)) {
+            (Some(parent), _) => Self::resolve_chain(profiles, &parent, chain)?,
+            (None, Some(builtin)) => builtin,
+            (None, None) if table.is_some() => Self::resolve_chain(profiles, "normal", chain)?,
+            (None, None) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("no profile named {}", name),
+                ))
+            }
+        };
+        Ok(match table {
+            Some(table) => table.merge(&base),
+            None => base,
+        })
+    }
+}
+fn concat(base: &Option<Vec<String>>, extra: &Option<Vec<String>>) -> Option<Vec<String>> {
+    match (base, extra) {
+        (Some(base), Some(extra)) => Some(base.iter().chain(extra.iter()).cloned().collect()),
+        (Some(list), None) | (None, Some(list)) => Some(list.clone()),
+        (None, None) => None,
+    }
+}
+///settings from the [build] table of build.toml
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BuildSettings {
+    ///how many translation units are compiled at once, defaults to the number of cpus
+    pub jobs: Option<usize>,
+    ///writes compile_commands.json on every build, the same as passing --compile-commands
+    pub compile_commands: Option<bool>,
+}
+///the [install] table, how a package that is not built by cppbuild installs itself
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct InstallSettings {
+    ///shell packages: the script that builds the package and installs it to $PREFIX
+    pub script: Option<String>,
+    ///make packages: extra arguments passed to both make and make install
+    pub make_args: Option<Vec<String>>,
+    ///raw packages: the directory holding the prebuilt headers, include by default
+    pub include_dir: Option<String>,
+    ///raw packages: the directory holding the prebuilt libraries, lib by default
+    pub lib_dir: Option<String>,
+    ///the libraries the package provides, written to its .pc file, the name of the package by default
+    pub libs: Option<Vec<String>>,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Example {
@@ -64,6 +209,9 @@ pub struct Item {
     comment: Option<String>,
     full_text: String,
     kind: ItemType,
+    ///what a function returns, the harness decides from it whether the value means the test failed
+    #[serde(default)]
+    result_type: Option<String>,
 }
 impl Item {
     pub fn new(
@@ -73,6 +221,7 @@ impl Item {
         start: usize,
         end: usize,
         kind: ItemType,
+        result_type: Option<String>,
     ) -> std::result::Result<Self, std::io::Error> {
         let mut file = File::open(path)?;
         let mut content: Vec<u8> = Vec::new();
@@ -88,7 +237,8 @@ impl Item {
             name,
             comment,
             full_text,
-            kind
+            kind,
+            result_type,
         })
     }
     pub fn get_type(&self) -> ItemType{
@@ -100,7 +250,58 @@ impl Item {
     pub fn get_name(&self) -> Option<String>{
         self.name.clone()
     }
+    pub fn get_result_type(&self) -> Option<String>{
+        self.result_type.clone()
+    }
+    ///the words following the identifier on the first line of the doc comment, such as timeout=5s in ///test timeout=5s
+    pub fn get_annotations(&self) -> Vec<String>{
+        match &self.comment {
+            Some(comment) => comment.lines().next().unwrap_or("").split_whitespace().skip(1).map(|a| a.to_string()).collect(),
+            None => Vec::new(),
+        }
+    }
+    ///tests annotated with ignore are skipped unless --include-ignored is given
+    pub fn is_ignored(&self) -> bool{
+        self.get_annotations().iter().any(|a| a == "ignore")
+    }
+    ///the seconds the test may run for, set with timeout=<duration>
+    pub fn get_timeout(&self) -> Option<f64>{
+        let annotations = self.get_annotations();
+        let value = annotations.iter().find_map(|a| a.strip_prefix("timeout="))?;
+        match parse_duration(value) {
+            Some(seconds) => Some(seconds),
+            None => {
+                eprintln!("warning: {} has an invalid timeout {}, the default timeout is used", self.name.clone().unwrap_or_default(), value);
+                None
+            }
+        }
+    }
 }
+///reads a duration such as 5s, 250ms, 2m or a plain number of seconds
+pub fn parse_duration(value: &str) -> Option<f64>{
+    let (number, scale) = if let Some(ms) = value.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(s) = value.strip_suffix('s') {
+        (s, 1.0)
+    } else if let Some(m) = value.strip_suffix('m') {
+        (m, 60.0)
+    } else {
+        (value, 1.0)
+    };
+    match number.parse::<f64>() {
+        Ok(number) if number > 0.0 && number.is_finite() => Some(number * scale),
+        _ => None,
+    }
+}
+///checks if a doc comment marks an item, it has to start with ident followed by nothing or by annotations
+fn is_marked(comment: Option<String>, ident: &str) -> bool{
+    match comment {
+        Some(comment) => comment.lines().next().unwrap_or("").split_whitespace().next() == Some(ident),
+        None => false,
+    }
+}
+///the runner of the harness build_main generates, it calls cppbuild_harness::collect_tests for the tests to run
+const RUNNER: &str = include_str!("harness/runner.cpp");
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Test {
     name: String,
@@ -109,6 +310,7 @@ pub struct Test {
 }
 impl Test {
     ///reads from the file given in path and matches doc comments found to see if an identifier can be found, in the binary that is ///test
+    ///words after the identifier are annotations of the test, ///test timeout=5s gives the test five seconds and ///test ignore skips it
     pub fn from_file(path: &str, ident: &str) -> std::result::Result<Self, std::io::Error> {
         let project = Project::from_file(path)?;
         let clang = Clang::new().unwrap();
@@ -144,7 +346,7 @@ impl Test {
                 .get_children()
                 .into_iter()
                 .filter(|e|  { 
-                    e.get_kind() == EntityKind::FunctionDecl && e.get_comment() == Some(ident.to_string()) ||
+                    e.get_kind() == EntityKind::FunctionDecl && is_marked(e.get_comment(), ident) ||
                     e.get_kind() == EntityKind::InclusionDirective && !e.is_in_system_header()
                         || e.get_kind() == EntityKind::UsingDirective && !e.is_in_system_header()
                 })
@@ -171,7 +373,8 @@ impl Test {
                             EntityKind::FunctionDecl => ItemType::FunctionDecl,
                             EntityKind::UsingDirective => ItemType::UsingDirective,
                             _ => ItemType::InclusionDirective
-                        }
+                        },
+                        func.get_result_type().map(|t| t.get_display_name()),
                     )?);
             }
         }
@@ -195,10 +398,11 @@ impl Test {
     pub fn get_name(&self) -> String{
         self.name.clone()
     }
-    ///this function builds the main function that calls each test function
+    ///this function builds the harness that runs each test function and prints a summary of the results
+    ///a test fails when it throws, when it returns false or when it returns a number other than 0, void tests only fail by throwing
+    ///each test runs in its own process, a test that crashes or runs past its timeout fails without stopping the others
     pub fn build_main(&self) -> std::result::Result<(), std::io::Error> {
         let mut file = File::create(format!("{}/target/test_{}.cpp", self.dir, self.name))?;
-        let mut open: bool = false;
         let mut funcs = Vec::new();
         for item in self.entities.iter(){
             match item.get_type(){
@@ -208,32 +412,80 @@ impl Test {
                 ItemType::UsingDirective => {
                     file.write_all(format!("{};\n", item.get_text()).as_bytes())?
                 },
-                ItemType::FunctionDecl => {
-                    file.write_all(format!("int {};\n", item.get_name().unwrap()).as_bytes())?;
-                    funcs.push(item);
-                },
+                ItemType::FunctionDecl => funcs.push(item),
             }
         }
+        file.write_all(RUNNER.as_bytes())?;
+        let mut cases = Vec::new();
         for func in funcs.iter(){
-            if !open{
-                open = true;
-                file.write_all(b"int main(){\n")?;
+            let name = func.get_name().unwrap_or_default();
+            //the harness has nothing to pass to a test that takes arguments
+            let ident = match name.strip_suffix("()") {
+                Some(ident) => ident.to_string(),
+                None => {
+                    eprintln!("warning: test {} takes arguments, it is not run", name);
+                    continue;
+                }
+            };
+            //a test declared in a header and defined in a source file is found twice
+            if cases.iter().any(|(case, _, _)| case == &ident) {
+                continue;
             }
-            file.write_all(format!("\t{};\n", func.get_name().unwrap()).as_bytes())?;
+            let result_type = func.get_result_type().unwrap_or_else(|| "int".to_string());
+            let check = match result_type.as_str() {
+                "void" => format!("{}();\n    return std::string();", ident),
+                "bool" => format!("return {}() ? std::string() : std::string(\"returned false\");", ident),
+                _ => format!(
+                    "auto result = {}();\n    return result == 0 ? std::string() : \"returned \" + std::to_string(result);",
+                    ident
+                ),
+            };
+            file.write_all(format!("{} {}();\n", result_type, ident).as_bytes())?;
+            file.write_all(format!("static std::string cppbuild_run_{}() {{\n    {}\n}}\n", ident, check).as_bytes())?;
+            cases.push((ident, func.get_timeout().unwrap_or(0.0), func.is_ignored()));
+        }
+        file.write_all(b"std::vector<cppbuild_harness::TestCase> cppbuild_harness::collect_tests() {\n    return {\n")?;
+        for (ident, timeout, ignored) in cases.iter(){
+            file.write_all(format!("        {{\"{}\", cppbuild_run_{}, {:?}, {}}},\n", ident, ident, timeout, ignored).as_bytes())?;
         }
-        file.write_all(b"}\n")?;
+        file.write_all(b"    };\n}\n")?;
         Ok(())
     }
 }
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Dependency {
     name: String,
+    ///left out for git and path dependencies that accept whatever version they point at
+    #[serde(default)]
     version: String,
     url: Option<String>,
+    ///a repository cloned and built with the project, a local bare repository or file:// url works too
+    git: Option<String>,
+    ///the commit, tag or branch of git to check out, the default branch when none is set
+    rev: Option<String>,
+    tag: Option<String>,
+    branch: Option<String>,
+    ///another cppbuild project on disk built with the project, relative to the root of the project
+    path: Option<String>,
+    #[serde(rename = "static")]
+    statik: Option<bool>,
+    ///sha256 the downloaded archive must have, overrides the checksum published by the registry
+    checksum: Option<String>,
 }
 impl Dependency {
     pub fn new(name: String, version: String, url: Option<String>) -> Self {
-        Self { name, version, url }
+        Self {
+            name,
+            version,
+            url,
+            git: None,
+            rev: None,
+            tag: None,
+            branch: None,
+            path: None,
+            statik: None,
+            checksum: None,
+        }
     }
     pub fn get_name(&self) -> String {
         self.name.clone()
@@ -244,6 +496,35 @@ impl Dependency {
     pub fn get_url(&self) -> Option<String> {
         self.url.clone()
     }
+    pub fn get_git(&self) -> Option<String> {
+        self.git.clone()
+    }
+    pub fn get_rev(&self) -> Option<String> {
+        self.rev.clone()
+    }
+    pub fn get_tag(&self) -> Option<String> {
+        self.tag.clone()
+    }
+    pub fn get_branch(&self) -> Option<String> {
+        self.branch.clone()
+    }
+    pub fn get_path(&self) -> Option<String> {
+        self.path.clone()
+    }
+    ///git and path dependencies are built from source as part of the build of the project
+    pub fn is_local(&self) -> bool {
+        self.git.is_some() || self.path.is_some()
+    }
+    ///static = true links the dependency statically by passing --static to pkg-config
+    pub fn is_static(&self) -> Option<bool> {
+        self.statik
+    }
+    pub fn get_checksum(&self) -> Option<String> {
+        self.checksum.clone()
+    }
+    pub fn set_checksum(&mut self, checksum: Option<String>) {
+        self.checksum = checksum;
+    }
 }
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Owner {
@@ -255,6 +536,14 @@ pub struct Project {
     package: Package,
     examples: Option<Vec<Example>>,
     tests: Option<Test>,
+    #[serde(skip)]
+    workspace: Option<Workspace>,
+}
+///the keys read before deciding whether build.toml is a project or a workspace
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    name: Option<String>,
+    workspace: Option<WorkspaceSettings>,
 }
 impl Project {
     pub fn new(name: String, project_type: Option<String>, owners: Option<Vec<Owner>>) -> Self {
@@ -274,6 +563,7 @@ impl Project {
             ),
             examples: None,
             tests: None,
+            workspace: None,
         }
     }
     pub fn get_package(&self) -> Package {
@@ -313,12 +603,44 @@ impl Project {
             Ok(_) => (),
             Err(e) => return Err(e),
         }
+        let manifest: Manifest = match toml::from_str(content.as_str()) {
+            Ok(manifest) => manifest,
+            Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+        };
+        //a build.toml with only a [workspace] table has no package of its own, it is named after its directory
+        if let Some(settings) = manifest.workspace {
+            if manifest.name.is_some() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{}/build.toml has both a package and a [workspace] table, move the package to a member", path),
+                ));
+            }
+            let name = match std::fs::canonicalize(path)?.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => "workspace".to_string(),
+            };
+            return Ok(Self {
+                package: Package::new(name, "0.0.0".to_string(), "c++17".to_string(), None, Vec::new(), None),
+                examples: None,
+                tests: None,
+                workspace: Some(Workspace::load(path, &settings)?),
+            });
+        }
+        let package = match toml::from_str(content.as_str()) {
+            Ok(package) => package,
+            Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+        };
         Ok(Self {
-            package: toml::from_str(content.as_str()).unwrap(),
+            package,
             examples: None,
             tests: None,
+            workspace: None,
         })
     }
+    ///the members of a build.toml with a [workspace] table, None for a single project
+    pub fn get_workspace(&self) -> Option<&Workspace> {
+        self.workspace.as_ref()
+    }
 }
 
 impl Package {
@@ -335,12 +657,23 @@ impl Package {
             version,
             standard,
             project_type,
+            package_type: None,
             repository,
             owners,
             description: None,
             license: None,
+            defines: None,
+            cflags: None,
+            ldflags: None,
+            libs: None,
+            include_dirs: None,
             dependency: None,
             dev_dependency: None,
+            lib_kind: None,
+            profile: None,
+            build: None,
+            toolchain: None,
+            install: None,
         }
     }
     pub fn get_name(&self) -> String{
@@ -349,6 +682,70 @@ impl Package {
     pub fn get_version(&self) -> String{
         self.version.clone()
     }
+    ///the [[dependency]] entries of the package, empty if there are none
+    pub fn get_dependencies(&self) -> Vec<Dependency>{
+        match &self.dependency {
+            Some(deps) => deps.clone(),
+            None => Vec::new(),
+        }
+    }
+    ///libraries are built as shared objects unless lib_kind says otherwise
+    pub fn get_lib_kind(&self) -> Vec<LibKind>{
+        match &self.lib_kind {
+            Some(kinds) => kinds.clone(),
+            None => vec![LibKind::Shared],
+        }
+    }
+    ///the defines, flags, libraries and include directories set at the top of build.toml, every profile adds to them
+    pub fn get_flags(&self) -> Profile{
+        Profile {
+            defines: self.defines.clone(),
+            cflags: self.cflags.clone(),
+            ldflags: self.ldflags.clone(),
+            libs: self.libs.clone(),
+            include_dirs: self.include_dirs.clone(),
+            ..Profile::default()
+        }
+    }
+    ///the [profile.<name>] tables exactly as written, see Profile::resolve for the profile a build uses
+    pub fn get_profiles(&self) -> HashMap<String, Profile>{
+        match &self.profile {
+            Some(profiles) => profiles.clone(),
+            None => HashMap::new(),
+        }
+    }
+    pub fn get_build(&self) -> BuildSettings{
+        match &self.build {
+            Some(build) => build.clone(),
+            None => BuildSettings::default(),
+        }
+    }
+    pub fn get_toolchain(&self) -> ToolchainConfig{
+        match &self.toolchain {
+            Some(toolchain) => toolchain.clone(),
+            None => ToolchainConfig::default(),
+        }
+    }
+    ///packages are cppbuild projects unless package_type says otherwise
+    pub fn get_package_type(&self) -> PackageType{
+        self.package_type.unwrap_or_default()
+    }
+    pub fn set_package_type(&mut self, package_type: PackageType){
+        self.package_type = Some(package_type);
+    }
+    pub fn get_install(&self) -> InstallSettings{
+        match &self.install {
+            Some(install) => install.clone(),
+            None => InstallSettings::default(),
+        }
+    }
+    ///the libraries other projects link when they depend on this package
+    pub fn get_provided_libs(&self) -> Vec<String>{
+        match &self.get_install().libs {
+            Some(libs) => libs.clone(),
+            None => vec![self.name.clone()],
+        }
+    }
     pub fn get_description(&self) -> Option<String>{
         match &self.description {
             Some(desc) => Some(desc.clone()),
@@ -0,0 +1,147 @@
+use crate::config::Config;
+use crate::upstream::{get_arch, Index, RepoEntry};
+use std::fs::{create_dir_all, File};
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::path::PathBuf;
+///the registry used when config.toml does not list any
+const DEFAULT_REGISTRY: &str = "http://localhost/cppbuild";
+///where the index and archives of a registry are kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Url(String),
+    Directory(PathBuf),
+}
+///a place packages are published to, both kinds use the same layout of <arch>/index.json and <arch>/<name>-<version>.tar.gz
+#[derive(Debug, Clone)]
+pub struct Registry {
+    name: String,
+    location: Location,
+}
+impl Registry {
+    ///urls starting with http:// or https:// are fetched over http, anything else, including file:// urls, is a directory
+    pub fn new(name: &str, url: &str) -> Self {
+        let location = if url.starts_with("http://") || url.starts_with("https://") {
+            Location::Url(url.trim_end_matches('/').to_string())
+        } else {
+            Location::Directory(PathBuf::from(url.trim_start_matches("file://")))
+        };
+        Self {
+            name: name.to_string(),
+            location,
+        }
+    }
+    ///the registries listed in ~/.cppbuild/config.toml, or the default registry if there are none
+    pub fn configured() -> io::Result<Vec<Self>> {
+        let registries: Vec<Self> = Config::load()?
+            .get_registries()
+            .iter()
+            .map(|registry| Self::new(&registry.name, &registry.url))
+            .collect();
+        if registries.is_empty() {
+            return Ok(vec![Self::new("default", DEFAULT_REGISTRY)]);
+        }
+        Ok(registries)
+    }
+    ///the configured registry called name
+    pub fn find(name: &str) -> io::Result<Self> {
+        match Self::configured()?.into_iter().find(|r| r.name == name) {
+            Some(registry) => Ok(registry),
+            None => Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("no registry called {} in {}", name, Config::path()),
+            )),
+        }
+    }
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn get_location(&self) -> &Location {
+        &self.location
+    }
+    ///reads the index for the current architecture, every entry remembers it came from this registry
+    pub fn fetch_index(&self) -> io::Result<Index> {
+        let data = self.read("index.json")?;
+        let mut index: Index = match serde_json::from_slice(&data) {
+            Ok(index) => index,
+            Err(e) => return Err(io::Error::new(ErrorKind::InvalidData, format!("{}: {}", self.name, e))),
+        };
+        index.set_registry(&self.name);
+        Ok(index)
+    }
+    ///the compressed archive of one version of a package
+    pub fn fetch_archive(&self, name: &str, version: &str) -> io::Result<Vec<u8>> {
+        self.read(&format!("{}-{}.tar.gz", name, version))
+    }
+    ///the checksum published next to the archive, in the format written by sha256sum
+    pub fn fetch_checksum(&self, name: &str, version: &str) -> io::Result<String> {
+        let file = format!("{}-{}.tar.gz.sha256", name, version);
+        let data = match self.read(&file) {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("{} {} has no published checksum: {}", name, version, e),
+                ))
+            }
+        };
+        match String::from_utf8_lossy(&data).split_whitespace().next() {
+            Some(checksum) => Ok(checksum.to_string()),
+            None => Err(io::Error::new(ErrorKind::InvalidData, format!("{} is empty", file))),
+        }
+    }
+    ///copies archive into a directory registry and adds entry to its index, replacing an entry of the same version
+    pub fn publish(&self, archive: &str, entry: RepoEntry) -> io::Result<()> {
+        let dir = match &self.location {
+            Location::Directory(root) => root.join(get_arch()?),
+            Location::Url(url) => {
+                return Err(io::Error::new(
+                    ErrorKind::Other,
+                    format!("{} is served over http, only directory registries can be published to", url),
+                ))
+            }
+        };
+        create_dir_all(&dir)?;
+        let file_name = format!("{}-{}.tar.gz", entry.get_name(), entry.get_version());
+        std::fs::copy(archive, dir.join(&file_name))?;
+        if let Some(checksum) = entry.get_checksum() {
+            let mut file = File::create(dir.join(format!("{}.sha256", file_name)))?;
+            file.write_all(format!("{}  {}\n", checksum, file_name).as_bytes())?;
+        }
+        let mut index = if dir.join("index.json").exists() {
+            self.fetch_index()?
+        } else {
+            Index::new()
+        };
+        index.push(entry);
+        let json = match serde_json::to_string_pretty(&index) {
+            Ok(json) => json,
+            Err(e) => return Err(io::Error::new(ErrorKind::Other, e)),
+        };
+        //the index is replaced in one rename so others reading the shared directory never see half of it
+        let tmp = dir.join(format!("index.json.{}", std::process::id()));
+        File::create(&tmp)?.write_all(json.as_bytes())?;
+        std::fs::rename(&tmp, dir.join("index.json"))
+    }
+    ///reads a file below the directory of the current architecture
+    fn read(&self, file: &str) -> io::Result<Vec<u8>> {
+        match &self.location {
+            Location::Directory(root) => {
+                let mut data = Vec::new();
+                File::open(root.join(get_arch()?).join(file))?.read_to_end(&mut data)?;
+                Ok(data)
+            }
+            Location::Url(url) => {
+                let url = format!("{}/{}/{}", url, get_arch()?, file);
+                let response = match reqwest::blocking::get(url.as_str()).and_then(|r| r.error_for_status()) {
+                    Ok(response) => response,
+                    Err(e) => return Err(io::Error::new(ErrorKind::Other, format!("{}: {}", url, e))),
+                };
+                match response.bytes() {
+                    Ok(bytes) => Ok(bytes.to_vec()),
+                    Err(e) => Err(io::Error::new(ErrorKind::Other, format!("{}: {}", url, e))),
+                }
+            }
+        }
+    }
+}
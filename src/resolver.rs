@@ -0,0 +1,488 @@
+use crate::lockfile::Source;
+use crate::project::Dependency;
+use crate::upstream::{Index, PackageType, Version, VersionReq};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::io::ErrorKind;
+///picking a version can add requirements that change earlier picks, resolution gives up if it has not settled after this many rounds
+const MAX_ROUNDS: usize = 256;
+///a version of a package the resolver may pick
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    name: String,
+    version: Version,
+    source: Source,
+    registry: Option<String>,
+    checksum: Option<String>,
+    build_type: Option<PackageType>,
+    dependencies: Vec<Dependency>,
+}
+///a requirement on a package, requirer is the package that declared it or None for the root
+#[derive(Debug, Clone)]
+struct Requirement {
+    requirement: VersionReq,
+    requirer: Option<String>,
+}
+///one package of a build plan and the names of the packages it depends on
+#[derive(Debug, Clone)]
+pub struct PlannedPackage {
+    name: String,
+    version: Version,
+    source: Source,
+    registry: Option<String>,
+    checksum: Option<String>,
+    build_type: Option<PackageType>,
+    dependencies: Vec<String>,
+}
+impl PlannedPackage {
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn get_version(&self) -> Version {
+        self.version.clone()
+    }
+    pub fn get_source(&self) -> Source {
+        self.source
+    }
+    ///the registry the package is downloaded from, None for system libraries
+    pub fn get_registry(&self) -> Option<String> {
+        self.registry.clone()
+    }
+    ///sha256 of the archive as listed in the index of the registry
+    pub fn get_checksum(&self) -> Option<String> {
+        self.checksum.clone()
+    }
+    ///how a registry package is built once it is unpacked, None for system libraries
+    pub fn get_build_type(&self) -> Option<PackageType> {
+        self.build_type
+    }
+    pub fn get_dependencies(&self) -> &[String] {
+        self.dependencies.as_slice()
+    }
+}
+///every package needed by the root, each one comes after the packages it depends on
+#[derive(Debug, Clone)]
+pub struct BuildPlan {
+    packages: Vec<PlannedPackage>,
+}
+impl BuildPlan {
+    pub fn get_packages(&self) -> &[PlannedPackage] {
+        self.packages.as_slice()
+    }
+    pub fn find(&self, name: &str) -> Option<&PlannedPackage> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+}
+impl std::fmt::Display for BuildPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for package in self.packages.iter() {
+            writeln!(f, "{} {} ({})", package.name, package.version, package.source)?;
+        }
+        Ok(())
+    }
+}
+///walks the dependencies of registry packages and system pkg-config libraries and picks one version of each
+pub struct Resolver {
+    index: Option<Index>,
+    candidates: HashMap<String, Vec<Candidate>>,
+    ///searched for .pc files before PKG_CONFIG_PATH
+    pkg_config_paths: Vec<String>,
+}
+impl Resolver {
+    ///index is the registry, without one only libraries known to pkg-config are considered
+    pub fn new(index: Option<Index>) -> Self {
+        Self {
+            index,
+            candidates: HashMap::new(),
+            pkg_config_paths: Vec::new(),
+        }
+    }
+    ///a pkg-config probe that also searches pkg_config_paths
+    fn pkg_config(&self) -> pkg_config::Config {
+        let mut config = pkg_config::Config::new();
+        config.cargo_metadata(false);
+        for path in self.pkg_config_paths.iter() {
+            config.search_path(path);
+        }
+        config
+    }
+    ///picks the newest version of every package reachable from dependencies that satisfies every requirement on it
+    ///when the requirements on a package conflict, an older version of one of the packages requiring it is tried instead
+    ///root names the project in the chain of requirers printed for conflicts
+    pub fn resolve(&mut self, root: &str, dependencies: &[Dependency]) -> io::Result<BuildPlan> {
+        let mut chosen: HashMap<String, Candidate> = HashMap::new();
+        let mut excluded: HashSet<(String, String)> = HashSet::new();
+        'rounds: for _ in 0..MAX_ROUNDS {
+            //requirements are collected again each round so packages that are no longer picked stop constraining others
+            let requirements = collect_requirements(dependencies, &chosen)?;
+            let mut next = HashMap::new();
+            for (name, requirers) in requirements.iter() {
+                match self.pick(name, requirers, &excluded) {
+                    Some(candidate) => {
+                        next.insert(name.clone(), candidate);
+                    }
+                    None => {
+                        for requirer in requirers.iter().filter_map(|r| r.requirer.as_ref()) {
+                            let key = (requirer.clone(), chosen[requirer].version.get_raw());
+                            excluded.insert(key.clone());
+                            if self.pick(requirer, &requirements[requirer], &excluded).is_some() {
+                                chosen.remove(requirer);
+                                continue 'rounds;
+                            }
+                            excluded.remove(&key);
+                        }
+                        return Err(conflict(root, name, self.candidates(name), &requirements, &chosen));
+                    }
+                }
+            }
+            if same_selection(&chosen, &next) {
+                return order(&next);
+            }
+            chosen = next;
+        }
+        Err(io::Error::new(
+            ErrorKind::Other,
+            format!("dependency resolution did not settle after {} rounds", MAX_ROUNDS),
+        ))
+    }
+    ///the newest version of name that satisfies every requirement and has not been ruled out by a conflict
+    fn pick(&mut self, name: &str, requirers: &[Requirement], excluded: &HashSet<(String, String)>) -> Option<Candidate> {
+        self.candidates(name)
+            .iter()
+            .filter(|c| !excluded.contains(&(c.name.clone(), c.version.get_raw())))
+            .find(|c| requirers.iter().all(|r| r.requirement.matches(&c.version)))
+            .cloned()
+    }
+    ///the versions of name known to pkg-config and the registry, newest first
+    ///an installed library stays ahead of a registry package of the same version
+    fn candidates(&mut self, name: &str) -> &[Candidate] {
+        if !self.candidates.contains_key(name) {
+            let mut found = Vec::new();
+            let config = self.pkg_config();
+            if let Ok(lib) = config.probe(name) {
+                if let Ok(version) = Version::parse(&lib.version) {
+                    found.push(Candidate {
+                        name: name.to_string(),
+                        version,
+                        source: Source::System,
+                        registry: None,
+                        checksum: None,
+                        build_type: None,
+                        dependencies: system_requires(&config, name),
+                    });
+                }
+            }
+            if let Some(index) = &self.index {
+                for package in index.versions(name) {
+                    if let Ok(version) = Version::parse(&package.get_version()) {
+                        found.push(Candidate {
+                            name: name.to_string(),
+                            version,
+                            source: Source::Registry,
+                            registry: package.get_registry(),
+                            checksum: package.get_checksum(),
+                            build_type: Some(package.get_build_type()),
+                            dependencies: package.get_dependencies(),
+                        });
+                    }
+                }
+            }
+            //sort is stable so system libraries stay first
+            found.sort_by(|a, b| b.version.cmp(&a.version));
+            self.candidates.insert(name.to_string(), found);
+        }
+        self.candidates[name].as_slice()
+    }
+}
+///the packages listed in Requires and Requires.private of the .pc file of an installed library
+fn system_requires(config: &pkg_config::Config, name: &str) -> Vec<Dependency> {
+    let mut requires: Vec<Dependency> = Vec::new();
+    for private in [false, true].iter() {
+        for line in config.requires(name, *private).unwrap_or_default() {
+            let depend = pc_requirement(&line);
+            if !requires.iter().any(|r| r.get_name() == depend.get_name()) {
+                requires.push(depend);
+            }
+        }
+    }
+    requires
+}
+///turns an entry of Requires such as glib-2.0 >= 2.50 into a dependency, != cannot be expressed so it accepts any version
+fn pc_requirement(entry: &str) -> Dependency {
+    let words: Vec<&str> = entry.split_whitespace().collect();
+    let requirement = match (words.get(1), words.get(2)) {
+        (Some(&op), Some(version)) if [">=", "<=", ">", "<", "="].contains(&op) => format!("{}{}", op, version),
+        _ => "*".to_string(),
+    };
+    Dependency::new(words.get(0).unwrap_or(&"").to_string(), requirement, None)
+}
+///the requirements on every package reachable from dependencies through the packages picked so far
+///requirements are gathered breadth first so the first requirer of a package is on the shortest chain from the root
+fn collect_requirements(
+    dependencies: &[Dependency],
+    chosen: &HashMap<String, Candidate>,
+) -> io::Result<BTreeMap<String, Vec<Requirement>>> {
+    let mut requirements: BTreeMap<String, Vec<Requirement>> = BTreeMap::new();
+    let mut queue: Vec<(Option<String>, Dependency)> = dependencies.iter().map(|d| (None, d.clone())).collect();
+    let mut visited = HashSet::new();
+    let mut next = 0;
+    while next < queue.len() {
+        let (requirer, depend) = queue[next].clone();
+        next += 1;
+        let name = depend.get_name();
+        let requirement = match VersionReq::parse(&depend.get_version()) {
+            Ok(requirement) => requirement,
+            Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", name, e))),
+        };
+        requirements
+            .entry(name.clone())
+            .or_insert_with(Vec::new)
+            .push(Requirement { requirement, requirer });
+        if visited.insert(name.clone()) {
+            if let Some(candidate) = chosen.get(&name) {
+                for dependency in candidate.dependencies.iter() {
+                    queue.push((Some(name.clone()), dependency.clone()));
+                }
+            }
+        }
+    }
+    Ok(requirements)
+}
+fn same_selection(a: &HashMap<String, Candidate>, b: &HashMap<String, Candidate>) -> bool {
+    a.len() == b.len()
+        && a.iter().all(|(name, candidate)| match b.get(name) {
+            Some(other) => other.version == candidate.version && other.source == candidate.source,
+            None => false,
+        })
+}
+///the chain of packages from the root to name, such as app -> opencv 4.2.0
+fn chain(root: &str, name: &str, requirements: &BTreeMap<String, Vec<Requirement>>, chosen: &HashMap<String, Candidate>) -> String {
+    let mut links = Vec::new();
+    let mut current = Some(name.to_string());
+    while let Some(package) = current {
+        if links.iter().any(|link: &(String, String)| link.0 == package) {
+            break;
+        }
+        let label = match chosen.get(&package) {
+            Some(candidate) => format!("{} {}", package, candidate.version),
+            None => package.clone(),
+        };
+        current = requirements
+            .get(&package)
+            .and_then(|r| r.first())
+            .and_then(|r| r.requirer.clone());
+        links.push((package, label));
+    }
+    let mut chain = vec![root.to_string()];
+    chain.extend(links.into_iter().rev().map(|link| link.1));
+    chain.join(" -> ")
+}
+///explains why no version of name could be picked, listing every requirement with the chain of packages that led to it
+fn conflict(
+    root: &str,
+    name: &str,
+    candidates: &[Candidate],
+    requirements: &BTreeMap<String, Vec<Requirement>>,
+    chosen: &HashMap<String, Candidate>,
+) -> io::Error {
+    let requirers: Vec<String> = requirements[name]
+        .iter()
+        .map(|r| match &r.requirer {
+            Some(requirer) => format!("\t{} requires {} {}", chain(root, requirer, requirements, chosen), name, r.requirement),
+            None => format!("\t{} requires {} {}", root, name, r.requirement),
+        })
+        .collect();
+    if candidates.is_empty() {
+        return io::Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "{} was not found by pkg-config or in the registry\n{}",
+                name,
+                requirers.join("\n")
+            ),
+        );
+    }
+    let available: Vec<String> = candidates
+        .iter()
+        .map(|c| format!("{} ({})", c.version, c.source))
+        .collect();
+    io::Error::new(
+        ErrorKind::Other,
+        format!(
+            "no version of {} satisfies every requirement\n{}\navailable: {}",
+            name,
+            requirers.join("\n"),
+            available.join(", ")
+        ),
+    )
+}
+///orders the picked packages so dependencies come before the packages that need them
+fn order(chosen: &HashMap<String, Candidate>) -> io::Result<BuildPlan> {
+    let mut names: Vec<&String> = chosen.keys().collect();
+    names.sort();
+    let mut packages = Vec::new();
+    let mut done = HashSet::new();
+    let mut path = Vec::new();
+    for name in names {
+        visit(name, chosen, &mut done, &mut path, &mut packages)?;
+    }
+    Ok(BuildPlan { packages })
+}
+fn visit(
+    name: &str,
+    chosen: &HashMap<String, Candidate>,
+    done: &mut HashSet<String>,
+    path: &mut Vec<String>,
+    packages: &mut Vec<PlannedPackage>,
+) -> io::Result<()> {
+    if done.contains(name) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|p| p == name) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(name.to_string());
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("packages depend on each other: {}", cycle.join(" -> ")),
+        ));
+    }
+    let candidate = &chosen[name];
+    path.push(name.to_string());
+    let mut dependencies = Vec::new();
+    for dependency in candidate.dependencies.iter() {
+        if chosen.contains_key(&dependency.get_name()) {
+            visit(&dependency.get_name(), chosen, done, path, packages)?;
+            dependencies.push(dependency.get_name());
+        }
+    }
+    path.pop();
+    done.insert(name.to_string());
+    packages.push(PlannedPackage {
+        name: candidate.name.clone(),
+        version: candidate.version.clone(),
+        source: candidate.source,
+        registry: candidate.registry.clone(),
+        checksum: candidate.checksum.clone(),
+        build_type: candidate.build_type,
+        dependencies,
+    });
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::{pc_requirement, Resolver};
+    use crate::project::Dependency;
+    use crate::upstream::{Index, PackageType, RepoEntry};
+    use std::io::Write;
+    fn depend(name: &str, requirement: &str) -> Dependency {
+        Dependency::new(name.to_string(), requirement.to_string(), None)
+    }
+    fn entry(name: &str, version: &str, dependencies: Vec<Dependency>) -> RepoEntry {
+        RepoEntry::new(name.to_string(), version.to_string(), None, dependencies, PackageType::CppBuild)
+    }
+    fn index(entries: Vec<RepoEntry>) -> Index {
+        let mut index = Index::new();
+        for entry in entries {
+            index.push(entry);
+        }
+        index
+    }
+    fn versions(resolver: &mut Resolver, dependencies: &[Dependency]) -> Vec<String> {
+        let plan = resolver.resolve("app", dependencies).unwrap();
+        plan.get_packages()
+            .iter()
+            .map(|p| format!("{} {}", p.get_name(), p.get_version()))
+            .collect()
+    }
+    #[test]
+    fn dependencies_come_first() {
+        let mut resolver = Resolver::new(Some(index(vec![
+            entry("cppbuild-test-top", "1.0.0", vec![depend("cppbuild-test-base", "^2")]),
+            entry("cppbuild-test-base", "2.1.0", Vec::new()),
+            entry("cppbuild-test-base", "3.0.0", Vec::new()),
+        ])));
+        assert_eq!(
+            versions(&mut resolver, &[depend("cppbuild-test-top", "*")]),
+            ["cppbuild-test-base 2.1.0", "cppbuild-test-top 1.0.0"]
+        );
+    }
+    #[test]
+    fn backtracks_to_an_older_requirer() {
+        //the newest a needs c 2, which the root rules out, so the older a is picked instead
+        let mut resolver = Resolver::new(Some(index(vec![
+            entry("cppbuild-test-a", "1.1.0", vec![depend("cppbuild-test-c", "^2")]),
+            entry("cppbuild-test-a", "1.0.0", vec![depend("cppbuild-test-c", "^1")]),
+            entry("cppbuild-test-c", "1.0.0", Vec::new()),
+            entry("cppbuild-test-c", "2.0.0", Vec::new()),
+        ])));
+        assert_eq!(
+            versions(
+                &mut resolver,
+                &[depend("cppbuild-test-a", "^1"), depend("cppbuild-test-c", "^1")]
+            ),
+            ["cppbuild-test-c 1.0.0", "cppbuild-test-a 1.0.0"]
+        );
+    }
+    #[test]
+    fn conflict_lists_every_requirement() {
+        let mut resolver = Resolver::new(Some(index(vec![
+            entry("cppbuild-test-left", "1.0.0", vec![depend("cppbuild-test-shared", "^1")]),
+            entry("cppbuild-test-right", "1.0.0", vec![depend("cppbuild-test-shared", "^2")]),
+            entry("cppbuild-test-shared", "1.0.0", Vec::new()),
+            entry("cppbuild-test-shared", "2.0.0", Vec::new()),
+        ])));
+        let error = resolver
+            .resolve(
+                "app",
+                &[depend("cppbuild-test-left", "^1"), depend("cppbuild-test-right", "^1")],
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "no version of cppbuild-test-shared satisfies every requirement\n\
+             \tapp -> cppbuild-test-left 1.0.0 requires cppbuild-test-shared ^1\n\
+             \tapp -> cppbuild-test-right 1.0.0 requires cppbuild-test-shared ^2\n\
+             available: 2.0.0 (registry), 1.0.0 (registry)"
+        );
+    }
+    #[test]
+    fn missing_package() {
+        let mut resolver = Resolver::new(Some(index(vec![entry(
+            "cppbuild-test-needy",
+            "1.0.0",
+            vec![depend("cppbuild-test-missing", "^1")],
+        )])));
+        let error = resolver.resolve("app", &[depend("cppbuild-test-needy", "*")]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "cppbuild-test-missing was not found by pkg-config or in the registry\n\
+             \tapp -> cppbuild-test-needy 1.0.0 requires cppbuild-test-missing ^1"
+        );
+    }
+    #[test]
+    fn pc_requirements() {
+        assert_eq!(pc_requirement("glib-2.0 >= 2.50").get_version(), ">=2.50");
+        assert_eq!(pc_requirement("zlib").get_version(), "*");
+        assert_eq!(pc_requirement("foo != 1.0").get_version(), "*");
+        assert_eq!(pc_requirement("foo = 1.0").get_name(), "foo");
+    }
+    #[test]
+    fn walks_the_requires_of_system_libraries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut pc = std::fs::File::create(dir.path().join("cppbuild-test-system.pc")).unwrap();
+        pc.write_all(
+            b"Name: cppbuild-test-system\nDescription: test\nVersion: 1.0.0\n\
+              Requires.private: cppbuild-test-from-registry >= 1.1\n",
+        )
+        .unwrap();
+        let mut fake = std::fs::File::create(dir.path().join("cppbuild-test-from-registry.pc")).unwrap();
+        //pkg-config only lists Requires it can find, the registry has a newer version than the one installed
+        fake.write_all(b"Name: cppbuild-test-from-registry\nDescription: test\nVersion: 1.1.0\n").unwrap();
+        let mut resolver = Resolver::new(Some(index(vec![entry("cppbuild-test-from-registry", "1.2.0", Vec::new())])));
+        resolver.pkg_config_paths.push(format!("{}", dir.path().display()));
+        assert_eq!(
+            versions(&mut resolver, &[depend("cppbuild-test-system", "*")]),
+            ["cppbuild-test-from-registry 1.2.0", "cppbuild-test-system 1.0.0"]
+        );
+    }
+}
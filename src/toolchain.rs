@@ -0,0 +1,252 @@
+use serde_derive::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Command;
+///the [toolchain] table of build.toml, every tool left out falls back to the environment or the gnu defaults
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ToolchainConfig {
+    pub cxx: Option<String>,
+    pub cc: Option<String>,
+    pub ar: Option<String>,
+    pub linker: Option<String>,
+}
+///compilers of the same family accept the same spelling of flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompilerFamily {
+    Gcc,
+    Clang,
+    Msvc,
+}
+impl CompilerFamily {
+    ///guesses the family from the name of the executable, asking the compiler for its version when the name is ambiguous such as c++
+    pub fn detect(compiler: &str) -> Self {
+        let name = match Path::new(compiler).file_stem() {
+            Some(stem) => stem.to_string_lossy().to_lowercase(),
+            None => compiler.to_lowercase(),
+        };
+        if name == "cl" || name == "clang-cl" {
+            return CompilerFamily::Msvc;
+        }
+        if name.contains("clang") {
+            return CompilerFamily::Clang;
+        }
+        if name.contains("g++") || name.contains("gcc") {
+            return CompilerFamily::Gcc;
+        }
+        match Command::new(compiler).arg("--version").output() {
+            Ok(op) if String::from_utf8_lossy(&op.stdout).contains("clang") => CompilerFamily::Clang,
+            _ => CompilerFamily::Gcc,
+        }
+    }
+    ///flag selecting the language standard, nostd projects get no standard library instead
+    pub fn std_flag(&self, standard: &str) -> Option<String> {
+        match (self, standard) {
+            (CompilerFamily::Msvc, "nostd") => None,
+            (CompilerFamily::Msvc, _) => Some(format!("/std:{}", standard)),
+            (_, "nostd") => Some("-nostd".to_string()),
+            (_, _) => Some(format!("-std={}", standard)),
+        }
+    }
+    pub fn include_flag(&self, dir: &str) -> String {
+        match self {
+            CompilerFamily::Msvc => format!("/I{}", dir),
+            _ => format!("-I{}", dir),
+        }
+    }
+    ///optimization flag for an opt_level of a profile, 0 to 3 or s and z to optimize for size
+    pub fn opt_flag(&self, level: &str) -> String {
+        match (self, level) {
+            (CompilerFamily::Msvc, "0") => "/Od".to_string(),
+            (CompilerFamily::Msvc, "1") | (CompilerFamily::Msvc, "s") | (CompilerFamily::Msvc, "z") => "/O1".to_string(),
+            (CompilerFamily::Msvc, _) => "/O2".to_string(),
+            (CompilerFamily::Gcc, "z") => "-Os".to_string(),
+            (_, _) => format!("-O{}", level),
+        }
+    }
+    pub fn debug_flag(&self) -> String {
+        match self {
+            CompilerFamily::Msvc => "/Zi".to_string(),
+            _ => "-g".to_string(),
+        }
+    }
+    ///link time optimization, passed both when compiling and when linking
+    pub fn lto_flag(&self) -> String {
+        match self {
+            CompilerFamily::Msvc => "/GL".to_string(),
+            _ => "-flto".to_string(),
+        }
+    }
+    ///defines a preprocessor macro, written as NAME or NAME=value
+    pub fn define_flag(&self, define: &str) -> String {
+        match self {
+            CompilerFamily::Msvc => format!("/D{}", define),
+            _ => format!("-D{}", define),
+        }
+    }
+    ///links against a library by name, lib_flag("m") gives -lm
+    pub fn lib_flag(&self, lib: &str) -> String {
+        match self {
+            CompilerFamily::Msvc => format!("{}.lib", lib),
+            _ => format!("-l{}", lib),
+        }
+    }
+    ///adds a directory the linker searches for libraries
+    pub fn link_path_flag(&self, dir: &str) -> String {
+        match self {
+            CompilerFamily::Msvc => format!("/LIBPATH:{}", dir),
+            _ => format!("-L{}", dir),
+        }
+    }
+    ///asks the compiler to write the headers a source includes to depfile, msvc has no equivalent so its objects are always rebuilt
+    pub fn depfile_flags(&self, depfile: &str) -> Vec<String> {
+        match self {
+            CompilerFamily::Msvc => Vec::new(),
+            _ => vec!["-MMD".to_string(), "-MF".to_string(), depfile.to_string()],
+        }
+    }
+    ///compiles source into the object file object without linking
+    pub fn compile_flags(&self, source: &str, object: &str) -> Vec<String> {
+        match self {
+            CompilerFamily::Msvc => vec!["/c".to_string(), source.to_string(), format!("/Fo{}", object)],
+            _ => vec![
+                "-c".to_string(),
+                source.to_string(),
+                "-o".to_string(),
+                object.to_string(),
+            ],
+        }
+    }
+    ///names the executable produced by the link step
+    pub fn output_flags(&self, output: &str) -> Vec<String> {
+        match self {
+            CompilerFamily::Msvc => vec![format!("/Fe{}", output)],
+            _ => vec!["-o".to_string(), output.to_string()],
+        }
+    }
+    ///objects linked into shared libraries need to be position independent
+    pub fn pic_flag(&self) -> Option<String> {
+        match self {
+            CompilerFamily::Msvc => None,
+            _ => Some("-fPIC".to_string()),
+        }
+    }
+    ///flags for the linker driver to produce a shared library with the given soname
+    pub fn shared_flags(&self, soname: &str) -> Vec<String> {
+        match self {
+            CompilerFamily::Msvc => vec!["/LD".to_string()],
+            _ => vec!["-shared".to_string(), format!("-Wl,-soname,{}", soname)],
+        }
+    }
+    ///arguments for the archiver to create a static library from objects
+    pub fn archive_flags(&self, output: &str, objects: &[String]) -> Vec<String> {
+        let mut flags = match self {
+            CompilerFamily::Msvc => vec![format!("/OUT:{}", output)],
+            _ => vec!["rcs".to_string(), output.to_string()],
+        };
+        flags.extend_from_slice(objects);
+        flags
+    }
+    fn default_cc(&self) -> &'static str {
+        match self {
+            CompilerFamily::Gcc => "gcc",
+            CompilerFamily::Clang => "clang",
+            CompilerFamily::Msvc => "cl",
+        }
+    }
+    fn default_ar(&self) -> &'static str {
+        match self {
+            CompilerFamily::Msvc => "lib",
+            _ => "ar",
+        }
+    }
+}
+///the programs used to compile, archive and link a project
+#[derive(Debug, Clone)]
+pub struct Toolchain {
+    cxx: String,
+    ///the c compiler and archiver set through CC, AR or build.toml, None uses the default of the family of cxx
+    cc: Option<String>,
+    ar: Option<String>,
+    linker: Option<String>,
+    family: CompilerFamily,
+}
+impl Toolchain {
+    ///each tool is taken from its environment variable (CXX, CC, AR) if set, then from the [toolchain] table, then the defaults of the family of the c++ compiler
+    pub fn new(config: &ToolchainConfig) -> Self {
+        let cxx = pick(std::env::var("CXX").ok(), &config.cxx).unwrap_or_else(|| "g++".to_string());
+        let family = CompilerFamily::detect(&cxx);
+        Self {
+            cxx,
+            cc: pick(std::env::var("CC").ok(), &config.cc),
+            ar: pick(std::env::var("AR").ok(), &config.ar),
+            linker: config.linker.clone(),
+            family,
+        }
+    }
+    ///overrides the c++ compiler, used by --compiler which takes priority over CXX and build.toml
+    ///a c compiler and archiver that were not set explicitly follow the family of the new compiler
+    pub fn set_cxx(&mut self, cxx: String) {
+        self.family = CompilerFamily::detect(&cxx);
+        self.cxx = cxx;
+    }
+    pub fn get_cxx(&self) -> String {
+        self.cxx.clone()
+    }
+    pub fn get_cc(&self) -> String {
+        match &self.cc {
+            Some(cc) => cc.clone(),
+            None => self.family.default_cc().to_string(),
+        }
+    }
+    pub fn get_ar(&self) -> String {
+        match &self.ar {
+            Some(ar) => ar.clone(),
+            None => self.family.default_ar().to_string(),
+        }
+    }
+    ///the program that links objects, defaults to the c++ compiler driver
+    pub fn get_linker(&self) -> String {
+        match &self.linker {
+            Some(linker) => linker.clone(),
+            None => self.cxx.clone(),
+        }
+    }
+    pub fn get_family(&self) -> CompilerFamily {
+        self.family
+    }
+}
+fn pick(env: Option<String>, config: &Option<String>) -> Option<String> {
+    match (env, config) {
+        (Some(value), _) if !value.is_empty() => Some(value),
+        (_, Some(value)) => Some(value.clone()),
+        _ => None,
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::{CompilerFamily, Toolchain};
+    fn gcc(cc: Option<&str>) -> Toolchain {
+        Toolchain {
+            cxx: "g++".to_string(),
+            cc: cc.map(|cc| cc.to_string()),
+            ar: None,
+            linker: None,
+            family: CompilerFamily::Gcc,
+        }
+    }
+    #[test]
+    fn default_tools_follow_the_compiler() {
+        let mut toolchain = gcc(None);
+        toolchain.set_cxx("clang++".to_string());
+        assert_eq!(toolchain.get_cc(), "clang");
+        assert_eq!(toolchain.get_ar(), "ar");
+        toolchain.set_cxx("cl".to_string());
+        assert_eq!(toolchain.get_cc(), "cl");
+        assert_eq!(toolchain.get_ar(), "lib");
+    }
+    #[test]
+    fn explicit_tools_are_kept() {
+        let mut toolchain = gcc(Some("gcc-12"));
+        toolchain.set_cxx("clang++".to_string());
+        assert_eq!(toolchain.get_cc(), "gcc-12");
+    }
+}
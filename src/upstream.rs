@@ -1,14 +1,24 @@
-use crate::project::Package;
+use crate::cache::Cache;
+use crate::lockfile::Source;
+use crate::project::{Dependency, Package, Profile};
+use crate::program::{copy_dir, run_tool, BuildMode, Program};
 use crate::project::Project;
+use crate::registry::Registry;
+use crate::resolver::Resolver;
+use crate::toolchain::Toolchain;
 use libflate::gzip::{Decoder, Encoder};
 use std::io::{Read, Write};
 use walkdir::WalkDir;
 
 use serde_derive::{Deserialize, Serialize};
-use std::fs::{create_dir, File};
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::fs::{create_dir, create_dir_all, File};
 use std::io;
 use std::io::ErrorKind;
-use std::path::Path;
+use std::ops::Bound;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
 use std::sync::Mutex;
 use tar::Archive;
 
@@ -31,137 +41,926 @@ lazy_static! {
     };
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+///one published version of a package in the index of a registry
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RepoEntry {
     name: String,
     version: String,
+    ///sha256 of the archive
+    checksum: Option<String>,
+    #[serde(default)]
+    dependency: Vec<Dependency>,
+    #[serde(default)]
     build_type: PackageType,
+    ///the registry the entry was read from, filled in when the index is fetched
+    #[serde(skip)]
+    registry: Option<String>,
+}
+impl RepoEntry {
+    pub fn new(
+        name: String,
+        version: String,
+        checksum: Option<String>,
+        dependency: Vec<Dependency>,
+        build_type: PackageType,
+    ) -> Self {
+        Self {
+            name,
+            version,
+            checksum,
+            dependency,
+            build_type,
+            registry: None,
+        }
+    }
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn get_version(&self) -> String {
+        self.version.clone()
+    }
+    pub fn get_checksum(&self) -> Option<String> {
+        self.checksum.clone()
+    }
+    pub fn get_dependencies(&self) -> Vec<Dependency> {
+        self.dependency.clone()
+    }
+    pub fn get_build_type(&self) -> PackageType {
+        self.build_type
+    }
+    pub fn get_registry(&self) -> Option<String> {
+        self.registry.clone()
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Index {
-    packages: Vec<Package>,
+    packages: Vec<RepoEntry>,
 }
 impl Index {
-    pub fn contains(&self, package: &Package) -> bool {
-        for pack in self.packages.iter() {
-            if pack == package {
-                return true;
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn contains(&self, name: &str, version: &str) -> bool {
+        self.packages.iter().any(|p| p.name == name && p.version == version)
+    }
+    ///adds entry, replacing the entry of the same name and version
+    pub fn push(&mut self, entry: RepoEntry) {
+        self.packages.retain(|p| p.name != entry.name || p.version != entry.version);
+        self.packages.push(entry);
+    }
+    ///adds the entries of other that are not in this index yet, so the first registry to publish a version wins
+    pub fn merge(&mut self, other: Index) {
+        for entry in other.packages.into_iter() {
+            if !self.contains(&entry.name, &entry.version) {
+                self.packages.push(entry);
             }
         }
-        false
+    }
+    pub fn set_registry(&mut self, registry: &str) {
+        for entry in self.packages.iter_mut() {
+            entry.registry = Some(registry.to_string());
+        }
+    }
+    ///every published version of the package called name
+    pub fn versions(&self, name: &str) -> Vec<RepoEntry> {
+        self.packages.iter().filter(|p| p.name == name).cloned().collect()
+    }
+    ///the newest package called name whose version satisfies requirement
+    pub fn select(&self, name: &str, requirement: &VersionReq) -> Option<RepoEntry> {
+        let mut best: Option<(Version, &RepoEntry)> = None;
+        for pack in self.packages.iter().filter(|p| p.name == name) {
+            let version = match Version::parse(&pack.get_version()) {
+                Ok(version) => version,
+                Err(_) => continue,
+            };
+            if !requirement.matches(&version) {
+                continue;
+            }
+            match &best {
+                Some((newest, _)) if *newest >= version => (),
+                _ => best = Some((version, pack)),
+            }
+        }
+        best.map(|(_, pack)| pack.clone())
     }
 }
-#[derive(Debug, PartialEq, Eq)]
+///a semantic version, versions with fewer than three numbers such as 2.0 are padded with zeros
+///anything after the third number, like the .SVN of 3.10.0.SVN, is kept in raw but ignored when comparing
+#[derive(Debug, Clone)]
 pub struct Version {
     raw: String,
+    major: u64,
+    minor: u64,
+    patch: u64,
+    pre: Vec<String>,
+}
+impl Version {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            raw: format!("{}.{}.{}", major, minor, patch),
+            major,
+            minor,
+            patch,
+            pre: Vec::new(),
+        }
+    }
+    pub fn parse(version: &str) -> io::Result<Self> {
+        Ok(Self::parse_parts(version)?.0)
+    }
+    ///parses a version and also returns how many of major, minor and patch were written, ^1.2 and ^1.2.0 differ for example
+    fn parse_parts(version: &str) -> io::Result<(Self, usize)> {
+        let raw = version.trim();
+        let without_build = raw.split('+').next().unwrap_or("");
+        let mut split = without_build.splitn(2, '-');
+        let numbers = split.next().unwrap_or("");
+        let pre: Vec<String> = match split.next() {
+            Some(pre) => pre.split('.').map(|p| p.to_string()).collect(),
+            None => Vec::new(),
+        };
+        let mut parts = [0u64; 3];
+        let mut given = 0;
+        for (index, part) in numbers.split('.').enumerate() {
+            if index >= 3 {
+                break;
+            }
+            parts[index] = match part.parse::<u64>() {
+                Ok(number) => number,
+                Err(_) => return Err(invalid_version(version)),
+            };
+            given += 1;
+        }
+        Ok((
+            Self {
+                raw: raw.to_string(),
+                major: parts[0],
+                minor: parts[1],
+                patch: parts[2],
+                pre,
+            },
+            given,
+        ))
+    }
+    pub fn get_major(&self) -> u64 {
+        self.major
+    }
+    pub fn get_minor(&self) -> u64 {
+        self.minor
+    }
+    pub fn get_patch(&self) -> u64 {
+        self.patch
+    }
+    pub fn is_prerelease(&self) -> bool {
+        !self.pre.is_empty()
+    }
+    ///the version as written with missing numbers padded, pkg-config considers 0.3.0 newer than 0.3
+    pub fn get_padded(&self) -> String {
+        let numbers = self.raw.split(|c| c == '-' || c == '+').next().unwrap_or("");
+        if numbers.split('.').count() >= 3 {
+            return self.raw.clone();
+        }
+        let mut padded = format!("{}.{}.{}", self.major, self.minor, self.patch);
+        if self.is_prerelease() {
+            padded = format!("{}-{}", padded, self.pre.join("."));
+        }
+        padded
+    }
+    ///the version exactly as it was written
+    pub fn get_raw(&self) -> String {
+        self.raw.clone()
+    }
+}
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Version {}
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Version {
+    ///a pre-release sorts before the release it leads up to, its identifiers are compared numerically when both are numbers
+    fn cmp(&self, other: &Self) -> Ordering {
+        let numbers = (self.major, self.minor, self.patch).cmp(&(other.major, other.minor, other.patch));
+        if numbers != Ordering::Equal {
+            return numbers;
+        }
+        match (self.pre.is_empty(), other.pre.is_empty()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => return Ordering::Greater,
+            (false, true) => return Ordering::Less,
+            (false, false) => (),
+        }
+        for (left, right) in self.pre.iter().zip(other.pre.iter()) {
+            let order = match (left.parse::<u64>(), right.parse::<u64>()) {
+                (Ok(l), Ok(r)) => l.cmp(&r),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => left.cmp(right),
+            };
+            if order != Ordering::Equal {
+                return order;
+            }
+        }
+        self.pre.len().cmp(&other.pre.len())
+    }
+}
+fn invalid_version(version: &str) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidInput,
+        format!("{} is not a valid version", version),
+    )
+}
+///a version requirement such as ^1.2, ~1.2.3, >=1.0, <2.0, 1.* or *
+///a bare version like 4.2.0 has to match exactly, the same as a dependency version always did
+#[derive(Debug, Clone)]
+pub struct VersionReq {
+    raw: String,
+    lower: Bound<Version>,
+    upper: Bound<Version>,
 }
-/*impl Version {
-    pub fn new(version: String) -> Self {
-        Self { raw: version }
+impl VersionReq {
+    pub fn parse(requirement: &str) -> io::Result<Self> {
+        let mut lower = Bound::Unbounded;
+        let mut upper = Bound::Unbounded;
+        for comparator in requirement.split(',') {
+            let comparator = comparator.trim();
+            if comparator.is_empty() || comparator == "*" {
+                continue;
+            }
+            let (low, high) = comparator_bounds(comparator)?;
+            lower = tighter(lower, low, Ordering::Greater);
+            upper = tighter(upper, high, Ordering::Less);
+        }
+        Ok(Self {
+            raw: requirement.trim().to_string(),
+            lower,
+            upper,
+        })
+    }
+    ///checks if version lies between the bounds of every comparator
+    pub fn matches(&self, version: &Version) -> bool {
+        let above = match &self.lower {
+            Bound::Included(low) => version >= low,
+            Bound::Excluded(low) => version > low,
+            Bound::Unbounded => true,
+        };
+        let below = match &self.upper {
+            Bound::Included(high) => version <= high,
+            Bound::Excluded(high) => version < high,
+            Bound::Unbounded => true,
+        };
+        above && below
+    }
+    ///the version when the requirement only accepts one, as with a bare version like 4.2.0
+    pub fn exact(&self) -> Option<Version> {
+        match (&self.lower, &self.upper) {
+            (Bound::Included(low), Bound::Included(high)) if low == high => Some(low.clone()),
+            _ => None,
+        }
     }
-    pub fn is_less(first: Version, second: Version) -> bool {
-        false
+    ///true for * which accepts any version
+    pub fn is_any(&self) -> bool {
+        self.lower == Bound::Unbounded && self.upper == Bound::Unbounded
     }
-    pub fn is_greater(first: Version, second: Version) -> bool {
-        false
+    ///the highest of versions that matches
+    pub fn select<'a, I: IntoIterator<Item = &'a Version>>(&self, versions: I) -> Option<&'a Version> {
+        versions.into_iter().filter(|v| self.matches(v)).max()
     }
-    pub fn is_equal(first: Version, second: Version) -> bool {
-        if first == second {
-            return true;
+    ///the requirement in the syntax of the Requires field of a .pc file, such as foo >= 1.2, foo < 2.0.0
+    pub fn pc_requires(&self, name: &str) -> String {
+        let mut constraints = Vec::new();
+        let (lower, upper) = self.pc_bounds();
+        match lower {
+            Bound::Included(version) => constraints.push(format!("{} >= {}", name, version)),
+            Bound::Excluded(version) => constraints.push(format!("{} > {}", name, version)),
+            Bound::Unbounded => (),
+        }
+        match upper {
+            Bound::Included(version) => constraints.push(format!("{} <= {}", name, version)),
+            Bound::Excluded(version) => constraints.push(format!("{} < {}", name, version)),
+            Bound::Unbounded => (),
         }
-        false
+        if constraints.is_empty() {
+            return name.to_string();
+        }
+        constraints.join(", ")
+    }
+    ///restricts a pkg-config probe to the versions this requirement accepts
+    pub fn configure(&self, config: &mut pkg_config::Config) {
+        let (lower, upper) = self.pc_bounds();
+        config.range_version((lower.as_ref().map(|v| v.as_str()), upper.as_ref().map(|v| v.as_str())));
+    }
+    ///the bounds as pkg-config compares them, versions are kept as they were written so 6.3 accepts a .pc with Version: 6.3
+    ///only an exclusive upper bound, like the one ^ and ~ compute, is padded to three numbers
+    fn pc_bounds(&self) -> (Bound<String>, Bound<String>) {
+        let upper = match &self.upper {
+            Bound::Excluded(version) => Bound::Excluded(version.get_padded()),
+            bound => bound.as_ref().map(|v| v.get_raw()),
+        };
+        (self.lower.as_ref().map(|v| v.get_raw()), upper)
+    }
+}
+impl std::fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
     }
-}*/
-#[derive(Debug, Serialize, Deserialize)]
+}
+///turns one comparator into the range of versions it accepts
+fn comparator_bounds(comparator: &str) -> io::Result<(Bound<Version>, Bound<Version>)> {
+    let operators = [">=", "<=", ">", "<", "=", "^", "~"];
+    let (op, rest) = match operators.iter().find(|op| comparator.starts_with(*op)) {
+        Some(op) => (*op, comparator[op.len()..].trim()),
+        None => ("", comparator),
+    };
+    //1.* and 1.2.x are treated like ~1 and ~1.2, ^0.0.x like ^0.0
+    //only a whole number can be a wildcard, the x of a pre-release such as 1.0.0-x86 is not one
+    let numbers = rest.split(|c| c == '-' || c == '+').next().unwrap_or("");
+    let wildcard = numbers.split('.').position(|part| part == "*" || part == "x" || part == "X");
+    let (op, rest) = match wildcard {
+        Some(index) => {
+            let given: Vec<&str> = numbers.split('.').take(index).collect();
+            let op = if op.is_empty() || op == "=" { "~" } else { op };
+            (op, given.join("."))
+        }
+        None => (op, rest.to_string()),
+    };
+    if rest.is_empty() {
+        return Ok((Bound::Unbounded, Bound::Unbounded));
+    }
+    let (version, given) = Version::parse_parts(&rest)?;
+    let (major, minor, patch) = (version.major, version.minor, version.patch);
+    Ok(match op {
+        ">=" => (Bound::Included(version), Bound::Unbounded),
+        ">" => (Bound::Excluded(version), Bound::Unbounded),
+        "<=" => (Bound::Unbounded, Bound::Included(version)),
+        "<" => (Bound::Unbounded, Bound::Excluded(version)),
+        "^" => {
+            let upper = if major > 0 || given == 1 {
+                Version::new(major + 1, 0, 0)
+            } else if minor > 0 || given == 2 {
+                Version::new(0, minor + 1, 0)
+            } else {
+                Version::new(0, 0, patch + 1)
+            };
+            (Bound::Included(version), Bound::Excluded(upper))
+        }
+        "~" => {
+            let upper = if given == 1 {
+                Version::new(major + 1, 0, 0)
+            } else {
+                Version::new(major, minor + 1, 0)
+            };
+            (Bound::Included(version), Bound::Excluded(upper))
+        }
+        _ => (Bound::Included(version.clone()), Bound::Included(version)),
+    })
+}
+///keeps whichever bound accepts fewer versions, wanted is Greater for lower bounds and Less for upper bounds
+fn tighter(current: Bound<Version>, new: Bound<Version>, wanted: Ordering) -> Bound<Version> {
+    let (current_version, new_version) = match (&current, &new) {
+        (Bound::Unbounded, _) => return new,
+        (_, Bound::Unbounded) => return current,
+        (Bound::Included(c), Bound::Included(n))
+        | (Bound::Included(c), Bound::Excluded(n))
+        | (Bound::Excluded(c), Bound::Included(n))
+        | (Bound::Excluded(c), Bound::Excluded(n)) => (c, n),
+    };
+    match new_version.cmp(current_version) {
+        Ordering::Equal => match new {
+            Bound::Excluded(_) => new,
+            _ => current,
+        },
+        order if order == wanted => new,
+        _ => current,
+    }
+}
+///how a package is built once it is fetched
+///cppbuild packages are built like any project, make packages run make and make install PREFIX=<prefix>,
+///shell packages run the script of their [install] table with $PREFIX set and raw packages ship prebuilt headers and libraries
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum PackageType {
+    //indexes written before build.toml could name a package type spell the variants capitalized
+    #[serde(alias = "CppBuild")]
     CppBuild,
+    #[serde(alias = "Make")]
     Make,
+    #[serde(alias = "Shell")]
     Shell,
+    #[serde(alias = "Raw")]
     Raw,
+    #[serde(alias = "PkgConfig")]
     PkgConfig,
 }
-pub fn download_packages(packages: &[(String, String)]) -> io::Result<()> {
-    /*let tmp_dir = match Builder::new().prefix("cppbuild").tempdir() {
-        Ok(dir) => dir,
-        Err(e) => panic!(e),
-    };*/
-    /*let res = reqwest::blocking::get("http://honeybucket.kittenz.pdx.edu/sources/index.json")
-        .unwrap()
-        .text()
-        .unwrap();
-    let packs: Index = toml::from_str(res.as_str()).unwrap();*/
-    for (name, version) in packages.iter() {
-        /*if packs.contains(&Package::new(
-            name.clone(),
-            version.clone(),
-            None,
-            None,
-            None,
-        )) {*/
-        println!("arch: {}", get_arch()?);
-        let response = reqwest::blocking::get(
-            format!(
-                "http://localhost/cppbuild/{}/{}-{}.tar.gz",
-                get_arch()?,
-                name,
-                version
-            )
-            .as_str(),
-        )
-        .unwrap();
-        //let mut file = File::create(format!("{}/{}-{}.tar.gz", PROGRAM_DATA.lock().unwrap(), name, version).as_str()).unwrap();
-        let bytes: Vec<u8> = response.bytes().unwrap().to_vec();
-        println!("bytes recv: {}", bytes.len());
-        //file.write_all(&bytes).unwrap();
-        //file.sync_all().unwrap();
-        let mut decoder = Decoder::new(&*bytes)?;
-        let mut decoded_data = Vec::new();
-        decoder.read_to_end(&mut decoded_data)?;
-        let mut a = Archive::new(&*decoded_data);
-        a.unpack(format!("{}/{}-{}/", PROGRAM_DATA.lock().unwrap(), name, version).as_str())?;
-        // }
+impl PackageType {
+    ///parses the name used in build.toml and on the command line, such as make or pkgconfig
+    pub fn from_name(name: &str) -> io::Result<Self> {
+        match name.to_lowercase().as_str() {
+            "cppbuild" => Ok(PackageType::CppBuild),
+            "make" => Ok(PackageType::Make),
+            "shell" => Ok(PackageType::Shell),
+            "raw" => Ok(PackageType::Raw),
+            "pkgconfig" | "pkg-config" => Ok(PackageType::PkgConfig),
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown package type {}, expected cppbuild, make, shell, raw or pkgconfig", name),
+            )),
+        }
+    }
+}
+impl Default for PackageType {
+    fn default() -> Self {
+        PackageType::CppBuild
+    }
+}
+///resolves each (name, version requirement) pair together with the dependencies of the packages it pulls in
+///then downloads every package the registries provide, dependencies first
+///a checksum pinned on one of dependencies is used instead of the one the registry publishes
+///cppbuild packages are built with toolchain and profile and installed once downloaded
+///with offline nothing is downloaded, the build fails before anything is installed unless every package is in the cache
+pub fn download_packages(
+    dependencies: &[Dependency],
+    toolchain: &Toolchain,
+    profile_name: &str,
+    profile: &Profile,
+    offline: bool,
+) -> io::Result<()> {
+    add_pkg_config_path();
+    let registries = Registry::configured()?;
+    let cache = Cache::open()?;
+    let plan = Resolver::new(Some(fetch_index(&registries, &cache, offline)?)).resolve("fetch", &dependencies)?;
+    print!("{}", plan);
+    let checksum = |name: &str, planned: Option<String>| {
+        dependencies
+            .iter()
+            .find(|d| d.get_name() == name)
+            .and_then(|d| d.get_checksum())
+            .or(planned)
+    };
+    if offline {
+        let mut missing = Vec::new();
+        for package in plan.get_packages().iter().filter(|p| p.get_source() == Source::Registry) {
+            let version = package.get_version().get_raw();
+            let expected = checksum(&package.get_name(), package.get_checksum());
+            if cache.find_archive(&package.get_name(), &version, expected.as_deref())?.is_none() {
+                missing.push(format!("{} {}", package.get_name(), version));
+            }
+        }
+        if !missing.is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("--offline is set and the cache {} is missing {}", cache.get_dir(), missing.join(", ")),
+            ));
+        }
+    }
+    for package in plan.get_packages().iter() {
+        if package.get_source() != Source::Registry {
+            continue;
+        }
+        let registry = match registries.iter().find(|r| Some(r.get_name()) == package.get_registry()) {
+            Some(registry) => registry,
+            None => {
+                return Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("no registry provides {} {}", package.get_name(), package.get_version()),
+                ))
+            }
+        };
+        download_package(
+            registry,
+            &cache,
+            &package.get_name(),
+            &package.get_version().get_raw(),
+            checksum(&package.get_name(), package.get_checksum()),
+            offline,
+        )?;
+        let source = format!(
+            "{}/{}-{}",
+            PROGRAM_DATA.lock().unwrap(),
+            package.get_name(),
+            package.get_version().get_raw()
+        );
+        match package.get_build_type() {
+            Some(kind) => install_package(&source, kind, toolchain, profile_name, profile)?,
+            None => println!("{} has no package type, it was unpacked to {}", package.get_name(), source),
+        }
+    }
+    Ok(())
+}
+///unpacks a single version of a package to ~/.cppbuild/<name>-<version>/, it is only downloaded from registry when it is not cached
+///the archive must match checksum, without one the <name>-<version>.tar.gz.sha256 file published next to the archive is used
+///a cached archive is only used without either of them when offline is set, with offline a missing archive is an error
+pub fn download_package(
+    registry: &Registry,
+    cache: &Cache,
+    name: &str,
+    version: &str,
+    checksum: Option<String>,
+    offline: bool,
+) -> io::Result<()> {
+    let checksum = match checksum {
+        None if !offline => Some(registry.fetch_checksum(name, version)?),
+        checksum => checksum,
+    };
+    let bytes = match cache.find_archive(name, version, checksum.as_deref())? {
+        Some(bytes) => bytes,
+        //the archive may have been corrupt or not match the pinned checksum, nothing is downloaded to replace it
+        None if offline => {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("--offline is set and the cache {} is missing {} {}", cache.get_dir(), name, version),
+            ))
+        }
+        None => {
+            let bytes: Vec<u8> = registry.fetch_archive(name, version)?;
+            println!("bytes recv: {}", bytes.len());
+            let expected = match checksum {
+                Some(checksum) => checksum,
+                None => registry.fetch_checksum(name, version)?,
+            };
+            verify_checksum(&format!("{}-{}.tar.gz", name, version), &bytes, &expected)?;
+            cache.store_archive(name, version, &bytes)?;
+            bytes
+        }
+    };
+    let mut decoder = Decoder::new(&*bytes)?;
+    let mut decoded_data = Vec::new();
+    decoder.read_to_end(&mut decoded_data)?;
+    unpack_archive(
+        &decoded_data,
+        &format!("{}/{}-{}/", PROGRAM_DATA.lock().unwrap(), name, version),
+    )?;
+    File::create(archive_checksum_path(name, version))?.write_all(sha256(&bytes).as_bytes())
+}
+///where download_package records the sha256 of the archive it unpacked, ~/.cppbuild/<name>-<version>.sha256
+fn archive_checksum_path(name: &str, version: &str) -> String {
+    format!("{}/{}-{}.sha256", PROGRAM_DATA.lock().unwrap(), name, version)
+}
+///the sha256 of the archive the installed registry package was unpacked from, it is the same on every machine
+pub fn installed_checksum(name: &str, version: &str) -> Option<String> {
+    match std::fs::read_to_string(archive_checksum_path(name, version)) {
+        Ok(checksum) if !checksum.trim().is_empty() => Some(checksum.trim().to_string()),
+        _ => None,
+    }
+}
+///compares the sha256 of data with expected, which may be upper or lower case hex
+pub fn verify_checksum(name: &str, data: &[u8], expected: &str) -> io::Result<()> {
+    let actual = sha256(data);
+    if actual != expected.trim().to_lowercase() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("checksum of {} is {} but {} was expected", name, actual, expected.trim()),
+        ));
+    }
+    Ok(())
+}
+///unpacks a tar archive into dest after checking every entry stays inside it
+///the whole archive is refused if any entry or link has an absolute path or a .. component
+pub fn unpack_archive(data: &[u8], dest: &str) -> io::Result<()> {
+    let mut archive = Archive::new(data);
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        check_archive_path(&path)?;
+        if let Some(link) = entry.link_name()? {
+            check_archive_path(&link)?;
+        }
+    }
+    create_dir_all(dest)?;
+    let mut archive = Archive::new(data);
+    for entry in archive.entries()? {
+        entry?.unpack_in(dest)?;
+    }
+    Ok(())
+}
+fn check_archive_path(path: &Path) -> io::Result<()> {
+    let unsafe_component = path.components().any(|c| match c {
+        Component::Prefix(_) | Component::RootDir | Component::ParentDir => true,
+        _ => false,
+    });
+    if unsafe_component {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("archive entry {} points outside of the package", path.display()),
+        ));
     }
     Ok(())
 }
 
-pub fn generate_package(kind: PackageType, path: &str) -> std::io::Result<()> {
+///reads the indexes of every registry into one, a registry that cannot be read is skipped with a warning
+///every index read is kept in cache, it is used instead when the registry cannot be reached and with offline
+pub fn fetch_index(registries: &[Registry], cache: &Cache, offline: bool) -> io::Result<Index> {
+    let mut index = Index::new();
+    let mut errors = Vec::new();
+    for registry in registries.iter() {
+        let found = if offline {
+            cache.load_index(&registry.get_name())
+        } else {
+            match registry.fetch_index() {
+                Ok(found) => {
+                    cache.store_index(&registry.get_name(), &found)?;
+                    Ok(found)
+                }
+                Err(e) => {
+                    println!("warning: registry {}: {}", registry.get_name(), e);
+                    cache.load_index(&registry.get_name())
+                }
+            }
+        };
+        match found {
+            Ok(found) => index.merge(found),
+            Err(e) => {
+                println!("warning: registry {}: {}", registry.get_name(), e);
+                errors.push(registry.get_name());
+            }
+        }
+    }
+    if !registries.is_empty() && errors.len() == registries.len() {
+        return Err(io::Error::new(
+            ErrorKind::Other,
+            format!("unable to read the index of {}", errors.join(", ")),
+        ));
+    }
+    Ok(index)
+}
+///packs the package at path, or the pkg-config library called path for pkgconfig packages, and adds it to the registry called registry
+pub fn publish_package(kind: PackageType, path: &str, registry: &str) -> io::Result<()> {
+    let registry = Registry::find(registry)?;
+    let (archive, project) = generate_package(kind, path)?;
+    let entry = RepoEntry::new(
+        project.get_name(),
+        project.get_version(),
+        Some(sha256_file(&archive)?),
+        project.get_package().get_dependencies(),
+        project.get_package().get_package_type(),
+    );
+    registry.publish(&archive, entry)?;
+    println!("published {} {} to {}", project.get_name(), project.get_version(), registry.get_name());
+    Ok(())
+}
+///checks a package has what its package_type needs before it is packed, so a broken package is never published
+pub fn check_package(project: &Project, path: &str) -> io::Result<()> {
+    let package = project.get_package();
+    let install = package.get_install();
+    //the registry has no way to fetch them for whoever installs the package
+    if let Some(depend) = package.get_dependencies().iter().find(|depend| depend.is_local()) {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: {} is a git or path dependency, those cannot be published", project.get_name(), depend.get_name()),
+        ));
+    }
+    let missing = match package.get_package_type() {
+        PackageType::CppBuild | PackageType::PkgConfig => None,
+        PackageType::Make => {
+            let makefiles = ["Makefile", "makefile", "GNUmakefile"];
+            if makefiles.iter().any(|file| Path::new(&format!("{}/{}", path, file)).exists()) {
+                None
+            } else {
+                Some("make packages need a Makefile".to_string())
+            }
+        }
+        PackageType::Shell => match &install.script {
+            Some(script) if Path::new(&format!("{}/{}", path, script)).is_file() => None,
+            Some(script) => Some(format!("the install script {} does not exist", script)),
+            None => Some("shell packages need script set in the [install] table".to_string()),
+        },
+        PackageType::Raw => {
+            let include_dir = install.include_dir.unwrap_or_else(|| "include".to_string());
+            let lib_dir = install.lib_dir.unwrap_or_else(|| "lib".to_string());
+            if Path::new(&format!("{}/{}", path, include_dir)).is_dir() || Path::new(&format!("{}/{}", path, lib_dir)).is_dir() {
+                None
+            } else {
+                Some(format!("raw packages need a {} or {} directory", include_dir, lib_dir))
+            }
+        }
+    };
+    match missing {
+        Some(message) => Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("{}: {}", project.get_name(), message),
+        )),
+        None => Ok(()),
+    }
+}
+///packs a package into target/<name>-<version>.tar.gz and returns the path of the archive and the manifest of the package
+///pkgconfig packages capture the installed library called path, every other kind packs the project at path
+pub fn generate_package(kind: PackageType, path: &str) -> std::io::Result<(String, Project)> {
     match kind {
-        PackageType::CppBuild => {
+        PackageType::CppBuild | PackageType::Make | PackageType::Shell | PackageType::Raw => {
             let project = Project::from_file(path)?;
+            let declared = project.get_package().get_package_type();
+            //the type on the command line may only repeat what build.toml says
+            if kind != PackageType::CppBuild && kind != declared {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("build.toml declares package_type {:?}", declared),
+                ));
+            }
+            check_package(&project, path)?;
             println!("path: {}", path);
-            compress(path, &project.get_name(), &project.get_version())?;
-            Ok(())
+            let archive = compress(path, &project.get_name(), &project.get_version())?;
+            Ok((archive, project))
         }
-        PackageType::Make => Ok(()),
-        PackageType::Shell => Ok(()),
-        PackageType::Raw => Ok(()),
         PackageType::PkgConfig => {
-            let conf = pkg_config::Config::new();
-            match conf.probe(path) {
-                Ok(lib) => {
-                    for link in lib.link_paths.iter() {
-                        // get link paths and for each file in them match it against library name
-                        for l in lib.libs.iter() {
-                            let path_name = format!("{}/{}", link.display(), l);
-                            let file: &Path = &Path::new(&path_name);
-                            if file.exists() && file.is_file() {
-                                // the object file exists
-                                //let mut src = File::open(file)?;
-                                //create the new file
-                                //let mut dst = File::create("")?;
-                            }
-                        }
+            let staging = capture_pkg_config(path)?;
+            let project = Project::from_file(&staging)?;
+            let archive = compress(&staging, &project.get_name(), &project.get_version())?;
+            Ok((archive, project))
+        }
+    }
+}
+///libraries of libc and the compiler runtime, every machine has its own so they are never captured
+const RUNTIME_LIBS: &[&str] = &["c", "m", "dl", "rt", "pthread", "util", "stdc++", "gcc_s"];
+///include directories shared by every library, the headers of one library cannot be told apart from the rest in them
+const SYSTEM_INCLUDE_DIRS: &[&str] = &["/usr/include", "/usr/local/include"];
+///copies the headers, libraries and .pc file of a library installed on this machine into target/pkgconfig/<name>/
+///headers keep their layout below includedir, libraries are put in lib and the .pc file in lib/pkgconfig
+pub fn capture_pkg_config(name: &str) -> io::Result<String> {
+    let lib = match pkg_config::Config::new().cargo_metadata(false).probe(name) {
+        Ok(lib) => lib,
+        Err(e) => return Err(io::Error::new(ErrorKind::NotFound, e)),
+    };
+    let variable = |variable: &str| pkg_config::get_variable(name, variable).unwrap_or_default();
+    let includedir = variable("includedir");
+    let libdir = variable("libdir");
+    let staging = format!("target/pkgconfig/{}", name);
+    if Path::new(&staging).exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    create_dir_all(format!("{}/lib/pkgconfig", staging))?;
+    create_dir_all(format!("{}/include", staging))?;
+    create_dir_all(format!("{}/target", staging))?;
+    //pkg-config leaves system directories out of the include paths, includedir is where the headers are installed to
+    let mut include_dirs = lib.include_paths.clone();
+    if !includedir.is_empty() && !include_dirs.iter().any(|dir| dir == Path::new(&includedir)) {
+        include_dirs.push(PathBuf::from(&includedir));
+    }
+    let mut captured = 0;
+    for dir in include_dirs.iter() {
+        let dir_name = format!("{}", dir.display());
+        if SYSTEM_INCLUDE_DIRS.contains(&dir_name.trim_end_matches('/')) {
+            let headers = shared_headers(dir, name);
+            if !headers.is_empty() {
+                println!(
+                    "warning: {} is shared with other libraries, only {} were captured from it",
+                    dir_name,
+                    headers.join(", ")
+                );
+            }
+            for header in headers.iter() {
+                let target = Path::new(&staging).join("include").join(header);
+                if let Some(parent) = target.parent() {
+                    create_dir_all(parent)?;
+                }
+                std::fs::copy(dir.join(header), target)?;
+            }
+            captured += headers.len();
+            continue;
+        }
+        let relative = dir.strip_prefix(&includedir).unwrap_or_else(|_| Path::new(""));
+        copy_dir(&dir_name, &format!("{}/include/{}", staging, relative.display()))?;
+        captured += WalkDir::new(dir).into_iter().filter_map(|e| e.ok()).filter(|e| e.file_type().is_file()).count();
+    }
+    if captured == 0 {
+        println!("warning: no headers of {} were found in {:?}, only its libraries are captured", name, include_dirs);
+    }
+    let mut search = lib.link_paths.clone();
+    if !libdir.is_empty() {
+        search.push(Path::new(&libdir).to_path_buf());
+    }
+    for name in lib.libs.iter().filter(|l| !RUNTIME_LIBS.contains(&l.as_str())) {
+        //lib<name>.a, lib<name>.so and every versioned lib<name>.so.<version>
+        let file_prefix = format!("lib{}.", name);
+        let mut found = false;
+        for dir in search.iter() {
+            let entries = match std::fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.filter_map(|e| e.ok()) {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let is_library = file_name.ends_with(".a") || file_name.ends_with(".so") || file_name.contains(".so.");
+                if file_name.starts_with(&file_prefix) && is_library {
+                    std::fs::copy(entry.path(), format!("{}/lib/{}", staging, file_name))?;
+                    found = true;
+                }
+            }
+            if found {
+                break;
+            }
+        }
+        if !found {
+            println!("warning: lib{} was not found in {:?} and is not captured", name, search);
+        }
+    }
+    let pc_file = format!("{}/{}.pc", variable("pcfiledir"), name);
+    let mut content = String::new();
+    File::open(&pc_file)?.read_to_string(&mut content)?;
+    let relocated = relocate_pc(&content, &variable("prefix"), &libdir, &includedir);
+    File::create(format!("{}/lib/pkgconfig/{}.pc", staging, name))?.write_all(relocated.as_bytes())?;
+    let mut package = Package::new(
+        name.to_string(),
+        lib.version.clone(),
+        "c++17".to_string(),
+        Some("lib".to_string()),
+        Vec::new(),
+        None,
+    );
+    package.set_package_type(PackageType::PkgConfig);
+    let manifest = match toml::to_string(&package) {
+        Ok(manifest) => manifest,
+        Err(e) => return Err(io::Error::new(ErrorKind::Other, e)),
+    };
+    File::create(format!("{}/build.toml", staging))?.write_all(manifest.as_bytes())?;
+    Ok(staging)
+}
+///the headers of name in an include directory other libraries share, relative to it
+///these are the files and directories named after name and every header they include with quotes, like the zconf.h of zlib.h
+fn shared_headers(dir: &Path, name: &str) -> Vec<String> {
+    let mut headers: Vec<String> = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            if !entry.file_name().to_string_lossy().starts_with(name) {
+                continue;
+            }
+            for file in WalkDir::new(entry.path()).into_iter().filter_map(|e| e.ok()) {
+                if let Ok(relative) = file.path().strip_prefix(dir) {
+                    if file.path().is_file() {
+                        headers.push(format!("{}", relative.display()));
                     }
-                    /*for i in lib.include_paths.iter() {
-                        // for every file in include_paths copy the file to the archive
-                    }*/
                 }
-                Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
             }
-            Ok(())
         }
     }
+    headers.sort();
+    let mut next = 0;
+    while next < headers.len() {
+        let header = dir.join(&headers[next]);
+        next += 1;
+        let content = std::fs::read_to_string(&header).unwrap_or_default();
+        for line in content.lines() {
+            let quoted = line
+                .trim()
+                .strip_prefix('#')
+                .and_then(|directive| directive.trim_start().strip_prefix("include"))
+                .and_then(|rest| rest.trim().strip_prefix('"'))
+                .and_then(|rest| rest.split('"').next());
+            let included = match quoted {
+                Some(included) if !Path::new(included).components().any(|c| c == Component::ParentDir) => included,
+                _ => continue,
+            };
+            //quoted includes are looked up next to the header first, then in dir
+            let beside = header.parent().unwrap_or(dir).join(&included);
+            let found = [beside, dir.join(&included)].iter().find(|path| path.is_file()).cloned();
+            if let Some(relative) = found.as_ref().and_then(|path| path.strip_prefix(dir).ok()) {
+                let relative = format!("{}", relative.display());
+                if !headers.contains(&relative) {
+                    headers.push(relative);
+                }
+            }
+        }
+    }
+    headers
+}
+///rewrites a .pc file for the layout of a captured package, every path becomes relative to ${pcfiledir}
+fn relocate_pc(content: &str, prefix: &str, libdir: &str, includedir: &str) -> String {
+    let mut relocated = String::new();
+    for line in content.lines() {
+        //variables are written name=value, fields such as Cflags: may contain = in their values
+        let variable = match (line.find('='), line.find(':')) {
+            (Some(equals), Some(colon)) if colon < equals => None,
+            (Some(equals), _) => Some(line[..equals].trim()),
+            _ => None,
+        };
+        let line = match variable {
+            Some("prefix") => "prefix=${pcfiledir}/../..".to_string(),
+            Some("exec_prefix") => "exec_prefix=${prefix}".to_string(),
+            Some("libdir") => "libdir=${prefix}/lib".to_string(),
+            Some("includedir") => "includedir=${prefix}/include".to_string(),
+            _ => {
+                //absolute paths left in other variables and fields point at the same directories
+                let mut line = line.to_string();
+                for (path, name) in [(includedir, "${includedir}"), (libdir, "${libdir}"), (prefix, "${prefix}")].iter() {
+                    if !path.is_empty() && *path != "/" {
+                        line = line.replace(path, name);
+                    }
+                }
+                line
+            }
+        };
+        relocated.push_str(&line);
+        relocated.push('\n');
+    }
+    relocated
 }
 pub fn compress(path: &str, name: &str, version: &str) -> std::io::Result<String> {
     use tar::Builder;
@@ -173,11 +972,13 @@ pub fn compress(path: &str, name: &str, version: &str) -> std::io::Result<String
             Ok(ent) => ent,
             Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
         };
-        let path = entry.path();
-        let filename = format!("{}", path.display());
-        if !filename.contains("target") && path.is_file() {
-            println!("{}", filename);
-            archive.append_file(path, &mut File::open(path)?)?;
+        let file = entry.path();
+        //entries are named relative to the package and the build output in its target directory is left out
+        let relative = file.strip_prefix(path).unwrap_or(file);
+        let in_target = relative.components().next() == Some(Component::Normal("target".as_ref()));
+        if !in_target && file.is_file() {
+            println!("{}", relative.display());
+            archive.append_file(relative, &mut File::open(file)?)?;
         }
     }
     archive.finish()?;
@@ -200,21 +1001,180 @@ pub fn compress(path: &str, name: &str, version: &str) -> std::io::Result<String
     std::fs::remove_file(tarpath.as_str())?;
     Ok(format!("{}.gz", tarpath))
 }
-pub fn generate_pc(package: &Package, path: &str) -> std::io::Result<()> {
-    let mut file = File::create(format!("{}/target/{}.pc", path, package.get_name()).as_str())?;
-    file.write_all(b"prefix=\n")?;
+///writes the .pc file of a package installed to prefix, the dependencies of the package become its Requires
+pub fn generate_pc(package: &Package, prefix: &str, path: &str) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(format!("prefix={}\n", prefix).as_bytes())?;
     file.write_all(b"exec_prefix=${prefix}\n")?;
-    file.write_all(format!("libdir={}/{}-{}/target/\n", "${prefix}", package.get_name(), package.get_version()).as_bytes())?;
-    file.write_all(format!("includedir={}/{}-{}/headers/\n", "${prefix}", package.get_name(), package.get_version()).as_bytes())?;
+    file.write_all(b"libdir=${exec_prefix}/lib\n")?;
+    file.write_all(b"includedir=${prefix}/include\n")?;
     file.write_all(format!("Name: {}\n", package.get_name()).as_bytes())?;
     file.write_all(format!("Version: {}\n", package.get_version()).as_bytes())?;
-    if let Some(desc) = package.get_description(){
-        file.write_all(format!("Description: {}\n", desc).as_bytes())?;
+    //pkg-config refuses files without a description
+    match package.get_description(){
+        Some(desc) => file.write_all(format!("Description: {}\n", desc).as_bytes())?,
+        None => file.write_all(format!("Description: {}\n", package.get_name()).as_bytes())?,
     };
-    file.write_all(format!("Libs: -L{} -l{}\n", "${libdir}", package.get_name()).as_bytes())?;
-    file.write_all(b"CFlags: -I${includedir}\n")?;
+    let mut requires = Vec::new();
+    for depend in package.get_dependencies().iter() {
+        requires.push(VersionReq::parse(&depend.get_version())?.pc_requires(&depend.get_name()));
+    }
+    if !requires.is_empty() {
+        file.write_all(format!("Requires: {}\n", requires.join(", ")).as_bytes())?;
+    }
+    //the rpath lets programs linked against the shared library find it without LD_LIBRARY_PATH
+    let mut libs = vec!["-L${libdir}".to_string(), "-Wl,-rpath,${libdir}".to_string()];
+    libs.extend(package.get_provided_libs().iter().map(|lib| format!("-l{}", lib)));
+    file.write_all(format!("Libs: {}\n", libs.join(" ")).as_bytes())?;
+    file.write_all(b"Cflags: -I${includedir}\n")?;
     Ok(())
 }
+///the directory the .pc files of installed packages are written to, Program::create adds it to PKG_CONFIG_PATH
+pub fn pkg_config_dir() -> String {
+    format!("{}/pkgconfig", PROGRAM_DATA.lock().unwrap())
+}
+///the prefix a fetched package is installed to, ~/.cppbuild/installed/<name>-<version>
+pub fn install_prefix(name: &str, version: &str) -> String {
+    format!("{}/installed/{}-{}", PROGRAM_DATA.lock().unwrap(), name, version)
+}
+///puts the directory of installed packages in front of PKG_CONFIG_PATH so they are found like system libraries
+pub fn add_pkg_config_path() {
+    prepend_pkg_config_path(&pkg_config_dir());
+}
+///puts dir in front of PKG_CONFIG_PATH unless it is already listed
+pub fn prepend_pkg_config_path(dir: &str) {
+    let paths = std::env::var("PKG_CONFIG_PATH").unwrap_or_default();
+    if paths.split(':').any(|path| path == dir) {
+        return;
+    }
+    if paths.is_empty() {
+        std::env::set_var("PKG_CONFIG_PATH", dir);
+    } else {
+        std::env::set_var("PKG_CONFIG_PATH", format!("{}:{}", dir, paths));
+    }
+}
+///builds the source of a fetched package of the given kind with the toolchain and profile of the consumer
+///then installs it to its prefix and registers its libraries with a .pc file
+pub fn install_package(
+    source: &str,
+    kind: PackageType,
+    toolchain: &Toolchain,
+    profile_name: &str,
+    profile: &Profile,
+) -> io::Result<()> {
+    let project = Project::from_file(source)?;
+    let prefix = install_prefix(&project.get_name(), &project.get_version());
+    let install = project.get_package().get_install();
+    //wrapped build systems get the toolchain the same way make and configure scripts expect it
+    let tool = |program: &str| {
+        let mut command = Command::new(program);
+        command
+            .current_dir(source)
+            .env("PREFIX", &prefix)
+            .env("CC", toolchain.get_cc())
+            .env("CXX", toolchain.get_cxx())
+            .env("AR", toolchain.get_ar())
+            .env("CPPBUILD_PROFILE", profile_name);
+        command
+    };
+    match kind {
+        PackageType::CppBuild => {
+            let mut program = Program::new(&project, source);
+            program.set_toolchain(toolchain.clone());
+            program.inherit_profile(profile_name, profile)?;
+            program.build(source, BuildMode::Normal)?;
+            program.install(source, &prefix)?;
+        }
+        PackageType::Make => {
+            let args = install.make_args.clone().unwrap_or_default();
+            let mut make = tool("make");
+            make.args(args.iter());
+            run_tool(make, &format!("building {}", project.get_name()))?;
+            let mut make_install = tool("make");
+            make_install
+                .arg("install")
+                .arg(format!("PREFIX={}", prefix))
+                .arg(format!("prefix={}", prefix))
+                .args(args.iter());
+            run_tool(make_install, &format!("installing {}", project.get_name()))?;
+        }
+        PackageType::Shell => {
+            let script = match &install.script {
+                Some(script) => script.clone(),
+                None => {
+                    return Err(io::Error::new(
+                        ErrorKind::NotFound,
+                        format!("{} has no install script", project.get_name()),
+                    ))
+                }
+            };
+            let mut shell = tool("sh");
+            shell.arg(script);
+            run_tool(shell, &format!("installing {}", project.get_name()))?;
+        }
+        PackageType::Raw => {
+            let include_dir = install.include_dir.clone().unwrap_or_else(|| "include".to_string());
+            let lib_dir = install.lib_dir.clone().unwrap_or_else(|| "lib".to_string());
+            for (from, to) in [(include_dir, "include"), (lib_dir, "lib")].iter() {
+                let from = format!("{}/{}", source, from);
+                if Path::new(&from).is_dir() {
+                    copy_dir(&from, &format!("{}/{}", prefix, to))?;
+                }
+            }
+        }
+        PackageType::PkgConfig => {
+            //captured libraries are already built, their relocatable .pc file is in lib/pkgconfig
+            copy_dir(&format!("{}/include", source), &format!("{}/include", prefix))?;
+            copy_dir(&format!("{}/lib", source), &format!("{}/lib", prefix))?;
+        }
+    }
+    if kind != PackageType::CppBuild || project.get_type() == "lib" {
+        register_pc(&project, &prefix)?;
+    }
+    println!("installed {} {} to {}", project.get_name(), project.get_version(), prefix);
+    Ok(())
+}
+///makes an installed package known to pkg-config, .pc files the package installed itself are used when there are any
+///${pcfiledir} in those files is replaced by the directory they were installed to, since the copy lives elsewhere
+fn register_pc(project: &Project, prefix: &str) -> io::Result<()> {
+    create_dir_all(pkg_config_dir())?;
+    let mut installed = false;
+    for dir in ["lib/pkgconfig", "share/pkgconfig"].iter() {
+        let dir = format!("{}/{}", prefix, dir);
+        for entry in WalkDir::new(&dir).max_depth(1).into_iter().filter_map(|e| e.ok()) {
+            if entry.path().extension().and_then(|ext| ext.to_str()) == Some("pc") {
+                let mut content = String::new();
+                File::open(entry.path())?.read_to_string(&mut content)?;
+                let mut file = File::create(Path::new(&pkg_config_dir()).join(entry.file_name()))?;
+                file.write_all(content.replace("${pcfiledir}", &dir).as_bytes())?;
+                installed = true;
+            }
+        }
+    }
+    if installed {
+        return Ok(());
+    }
+    generate_pc(
+        &project.get_package(),
+        prefix,
+        &format!("{}/{}.pc", pkg_config_dir(), project.get_name()),
+    )
+}
+///hex encoded sha256 of data, used as the checksum of locked dependencies and downloaded packages
+pub fn sha256(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    hasher
+        .result()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+pub fn sha256_file(path: &str) -> std::io::Result<String> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    Ok(sha256(&data))
+}
 pub fn get_arch() -> std::io::Result<String> {
     let rust_info = rust_info::get();
     Ok(format!(
@@ -237,3 +1197,187 @@ pub fn get_arch() -> std::io::Result<String> {
         }
     ))
 }
+#[cfg(test)]
+mod tests {
+    use super::{download_package, get_arch, sha256, shared_headers, Version, VersionReq};
+    use crate::cache::Cache;
+    use crate::registry::Registry;
+    use std::ops::Bound;
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+    fn matches(requirement: &str, version: &str) -> bool {
+        VersionReq::parse(requirement).unwrap().matches(&Version::parse(version).unwrap())
+    }
+    #[test]
+    fn parse_parts_counts_the_numbers_given() {
+        let (parsed, given) = Version::parse_parts("1.2").unwrap();
+        assert_eq!((parsed.get_major(), parsed.get_minor(), parsed.get_patch(), given), (1, 2, 0, 2));
+        let (parsed, given) = Version::parse_parts("3.10.0.SVN").unwrap();
+        assert_eq!((parsed.get_major(), parsed.get_minor(), parsed.get_patch(), given), (3, 10, 0, 3));
+        let (parsed, given) = Version::parse_parts(" 1.2.3-rc.1+build.5 ").unwrap();
+        assert_eq!((parsed.get_patch(), given), (3, 3));
+        assert_eq!(parsed.pre, ["rc", "1"]);
+        assert_eq!(parsed.get_raw(), "1.2.3-rc.1+build.5");
+        assert!(Version::parse_parts("1.a.3").is_err());
+        assert!(Version::parse_parts("").is_err());
+    }
+    #[test]
+    fn padding_keeps_the_pre_release() {
+        assert_eq!(version("0.3").get_padded(), "0.3.0");
+        assert_eq!(version("2-beta").get_padded(), "2.0.0-beta");
+        assert_eq!(version("1.2.3").get_padded(), "1.2.3");
+    }
+    #[test]
+    fn pre_releases_sort_before_the_release() {
+        let ordered = [
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+            "1.0.1",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(version(pair[0]) < version(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+        assert_eq!(version("1.0"), version("1.0.0"));
+        assert_eq!(version("1.0.0+build.1"), version("1.0.0"));
+    }
+    #[test]
+    fn pkg_config_bounds_keep_the_written_version() {
+        let requirement = VersionReq::parse("6.3").unwrap();
+        assert_eq!(requirement.pc_bounds().0, Bound::Included("6.3".to_string()));
+        assert_eq!(requirement.pc_requires("foo"), "foo >= 6.3, foo <= 6.3");
+        let requirement = VersionReq::parse("^6.3").unwrap();
+        assert_eq!(
+            requirement.pc_bounds(),
+            (Bound::Included("6.3".to_string()), Bound::Excluded("7.0.0".to_string()))
+        );
+        assert_eq!(VersionReq::parse(">=1.2, <2.0").unwrap().pc_requires("foo"), "foo >= 1.2, foo < 2.0.0");
+    }
+    #[test]
+    fn caret() {
+        assert!(matches("^1.2", "1.9.0"));
+        assert!(!matches("^1.2", "2.0.0"));
+        assert!(!matches("^1.2", "1.1.9"));
+        assert!(matches("^0.2.3", "0.2.9"));
+        assert!(!matches("^0.2.3", "0.3.0"));
+        assert!(matches("^0.0.3", "0.0.3"));
+        assert!(!matches("^0.0.3", "0.0.4"));
+        assert!(matches("^0.0.x", "0.0.7"));
+        assert!(!matches("^0.0.x", "0.1.0"));
+    }
+    #[test]
+    fn tilde() {
+        assert!(matches("~1.2.3", "1.2.3"));
+        assert!(matches("~1.2.3", "1.2.9"));
+        assert!(!matches("~1.2.3", "1.3.0"));
+        assert!(!matches("~1.2.3", "1.2.2"));
+        assert!(matches("~1", "1.9.0"));
+        assert!(!matches("~1", "2.0.0"));
+    }
+    #[test]
+    fn ranges() {
+        assert!(matches(">=1.0, <2.0", "1.0.0"));
+        assert!(matches(">=1.0, <2.0", "1.99.0"));
+        assert!(!matches(">=1.0, <2.0", "2.0.0"));
+        assert!(!matches(">=1.0, <2.0", "0.9.0"));
+        assert!(!matches(">1.0, <=1.5", "1.0.0"));
+        assert!(matches(">1.0, <=1.5", "1.5.0"));
+    }
+    #[test]
+    fn any() {
+        assert!(VersionReq::parse("*").unwrap().is_any());
+        assert!(matches("*", "0.0.1"));
+        assert!(matches("", "7.0.0"));
+    }
+    #[test]
+    fn wildcards() {
+        assert!(matches("1.*", "1.4.0"));
+        assert!(!matches("1.*", "2.0.0"));
+        assert!(matches("1.2.x", "1.2.8"));
+        assert!(!matches("1.2.X", "1.3.0"));
+    }
+    #[test]
+    fn exact_versions_with_an_x_in_the_pre_release() {
+        let requirement = VersionReq::parse("1.0.0-linux").unwrap();
+        assert_eq!(requirement.exact(), Some(version("1.0.0-linux")));
+        let requirement = VersionReq::parse("=2.0.0-x86").unwrap();
+        assert_eq!(requirement.exact(), Some(version("2.0.0-x86")));
+        assert!(!matches("=2.0.0-x86", "2.0.0"));
+    }
+    #[test]
+    fn bare_versions_match_exactly() {
+        assert_eq!(VersionReq::parse("4.2.0").unwrap().exact(), Some(version("4.2.0")));
+        assert!(!matches("4.2.0", "4.2.1"));
+    }
+    #[test]
+    fn shared_headers_follow_quoted_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("zlib.h"), "#include \"zconf.h\"\n#include <stdio.h>\n").unwrap();
+        std::fs::write(dir.path().join("zconf.h"), "#  include \"zutil.h\"\n").unwrap();
+        std::fs::write(dir.path().join("zutil.h"), "").unwrap();
+        std::fs::write(dir.path().join("stdio.h"), "").unwrap();
+        std::fs::write(dir.path().join("other.h"), "").unwrap();
+        assert_eq!(shared_headers(dir.path(), "zlib"), ["zlib.h", "zconf.h", "zutil.h"]);
+        assert!(shared_headers(dir.path(), "missing").is_empty());
+    }
+    #[test]
+    fn mismatched_pinned_checksum_rejects_the_archive() {
+        let registry_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let arch_dir = registry_dir.path().join(get_arch().unwrap());
+        std::fs::create_dir_all(&arch_dir).unwrap();
+        let archive = b"not really a tar.gz".to_vec();
+        std::fs::write(arch_dir.join("pinned-1.0.0.tar.gz"), &archive).unwrap();
+        //the registry publishes the right checksum, the pin in build.toml takes priority over it
+        std::fs::write(arch_dir.join("pinned-1.0.0.tar.gz.sha256"), sha256(&archive)).unwrap();
+        let registry = Registry::new("test", &format!("{}", registry_dir.path().display()));
+        let cache = Cache::new(&format!("{}", cache_dir.path().display()));
+        let pinned = "0".repeat(64);
+        let error = download_package(&registry, &cache, "pinned", "1.0.0", Some(pinned.clone()), false).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("checksum of pinned-1.0.0.tar.gz is {} but {} was expected", sha256(&archive), pinned)
+        );
+        assert!(cache.list().unwrap().is_empty());
+    }
+    #[test]
+    fn offline_does_not_download_a_missing_archive() {
+        let registry_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let arch_dir = registry_dir.path().join(get_arch().unwrap());
+        std::fs::create_dir_all(&arch_dir).unwrap();
+        let archive = b"an archive that is only in the registry".to_vec();
+        std::fs::write(arch_dir.join("uncached-1.0.0.tar.gz"), &archive).unwrap();
+        let registry = Registry::new("test", &format!("{}", registry_dir.path().display()));
+        let cache = Cache::new(&format!("{}", cache_dir.path().display()));
+        let error = download_package(&registry, &cache, "uncached", "1.0.0", Some(sha256(&archive)), true).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("--offline is set and the cache {} is missing uncached 1.0.0", cache.get_dir())
+        );
+        assert!(cache.list().unwrap().is_empty());
+    }
+    #[test]
+    fn cached_archive_is_checked_against_the_registry_when_online() {
+        let registry_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let arch_dir = registry_dir.path().join(get_arch().unwrap());
+        std::fs::create_dir_all(&arch_dir).unwrap();
+        let published = b"the archive the registry publishes now".to_vec();
+        std::fs::write(arch_dir.join("republished-1.0.0.tar.gz"), &published).unwrap();
+        std::fs::write(arch_dir.join("republished-1.0.0.tar.gz.sha256"), sha256(&published)).unwrap();
+        let registry = Registry::new("test", &format!("{}", registry_dir.path().display()));
+        let cache = Cache::new(&format!("{}", cache_dir.path().display()));
+        cache.store_archive("republished", "1.0.0", b"an archive cached before it was republished").unwrap();
+        //neither archive is a real tar.gz, unpacking fails once the published one was downloaded and cached
+        assert!(download_package(&registry, &cache, "republished", "1.0.0", None, false).is_err());
+        let cached = cache.find_archive("republished", "1.0.0", Some(&sha256(&published))).unwrap();
+        assert_eq!(cached, Some(published));
+    }
+}
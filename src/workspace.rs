@@ -0,0 +1,138 @@
+use crate::project::Project;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+///the [workspace] table, members are the directories of the projects below the root of the workspace
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct WorkspaceSettings {
+    pub members: Vec<String>,
+}
+///one project of a workspace, path is where its build.toml is
+#[derive(Debug)]
+pub struct Member {
+    path: String,
+    project: Project,
+}
+impl Member {
+    pub fn get_path(&self) -> String {
+        self.path.clone()
+    }
+    pub fn get_project(&self) -> &Project {
+        &self.project
+    }
+    pub fn get_name(&self) -> String {
+        self.project.get_name()
+    }
+}
+///several projects built together, every member builds into the target/ of the workspace
+#[derive(Debug)]
+pub struct Workspace {
+    root: String,
+    members: Vec<Member>,
+}
+impl Workspace {
+    ///loads the build.toml of every member listed in settings, paths are relative to root
+    pub fn load(root: &str, settings: &WorkspaceSettings) -> io::Result<Self> {
+        let mut members: Vec<Member> = Vec::new();
+        for dir in settings.members.iter() {
+            let path = format!("{}/{}", root, dir.trim_end_matches('/'));
+            let project = match Project::from_file(&path) {
+                Ok(project) => project,
+                Err(e) => return Err(io::Error::new(e.kind(), format!("workspace member {}: {}", dir, e))),
+            };
+            if project.get_workspace().is_some() {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("workspace member {} is a workspace itself", dir),
+                ));
+            }
+            if let Some(other) = members.iter().find(|m| m.get_name() == project.get_name()) {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} and {} are both called {}", other.path, path, project.get_name()),
+                ));
+            }
+            members.push(Member { path, project });
+        }
+        Ok(Self {
+            root: root.to_string(),
+            members,
+        })
+    }
+    ///the target/ shared by every member
+    pub fn target_dir(&self) -> String {
+        format!("{}/target", self.root)
+    }
+    pub fn get_members(&self) -> &[Member] {
+        self.members.as_slice()
+    }
+    ///the member with the given name, or the member in the directory name
+    pub fn find(&self, name: &str) -> io::Result<&Member> {
+        let by_path = format!("{}/{}", self.root, name.trim_end_matches('/'));
+        match self.members.iter().find(|m| m.get_name() == name || m.path == by_path) {
+            Some(member) => Ok(member),
+            None => {
+                let names: Vec<String> = self.members.iter().map(|m| m.get_name()).collect();
+                Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("no workspace member called {}, the members are {}", name, names.join(", ")),
+                ))
+            }
+        }
+    }
+    ///every member, each after the members it has path dependencies on
+    pub fn build_order(&self) -> io::Result<Vec<&Member>> {
+        let mut order = Vec::new();
+        let mut done = HashSet::new();
+        let mut path = Vec::new();
+        for member in self.members.iter() {
+            self.visit(member, &mut done, &mut path, &mut order)?;
+        }
+        Ok(order)
+    }
+    fn visit<'a>(
+        &'a self,
+        member: &'a Member,
+        done: &mut HashSet<String>,
+        path: &mut Vec<String>,
+        order: &mut Vec<&'a Member>,
+    ) -> io::Result<()> {
+        let name = member.get_name();
+        if done.contains(&name) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|p| p == &name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name);
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("workspace members depend on each other: {}", cycle.join(" -> ")),
+            ));
+        }
+        path.push(name.clone());
+        for depend in member.project.get_package().get_dependencies().iter() {
+            if let Some(dir) = depend.get_path() {
+                let dir = if Path::new(&dir).is_absolute() {
+                    dir
+                } else {
+                    format!("{}/{}", member.path, dir)
+                };
+                if let Some(dependency) = self.members.iter().find(|m| same_dir(&m.path, &dir)) {
+                    self.visit(dependency, done, path, order)?;
+                }
+            }
+        }
+        path.pop();
+        done.insert(name);
+        order.push(member);
+        Ok(())
+    }
+}
+fn same_dir(a: &str, b: &str) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}